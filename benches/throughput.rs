@@ -0,0 +1,200 @@
+//! Simple throughput benchmark, run with `cargo bench --bench throughput`.
+//!
+//! Compares rings whose storage is rounded up to a power of two (indices are masked) with compact
+//! rings of the same capacity (indices need a modulo).
+//!
+//! The two thread and ping pong cases are about the cache traffic between a writer and a reader
+//! on different cores. On a machine with a single core they only measure scheduling.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use atomic_ring_buffer::{
+    create_compact_ring_buffer, create_ring_buffer, BufferReader, BufferWriter,
+};
+
+const NUM_ELEMENTS: u64 = 20_000_000;
+const NUM_ROUND_TRIPS: u64 = 1_000_000;
+
+type RingConstructor = fn(usize) -> (BufferWriter<u64>, BufferReader<u64>);
+
+fn single_thread(create: RingConstructor, capacity: usize) -> Duration {
+    let (mut buffer_writer, mut buffer_reader) = create(capacity);
+
+    let tstart = Instant::now();
+
+    let mut idx = 0u64;
+
+    while idx < NUM_ELEMENTS {
+        while buffer_writer.try_write(idx).is_ok() {
+            idx += 1;
+        }
+
+        while let Ok(v) = buffer_reader.try_read() {
+            black_box(v);
+        }
+    }
+
+    tstart.elapsed()
+}
+
+fn single_thread_batched(create: RingConstructor, capacity: usize) -> Duration {
+    let (mut buffer_writer, mut buffer_reader) = create(capacity);
+
+    let tstart = Instant::now();
+
+    let mut values = 0..NUM_ELEMENTS;
+
+    while !values.is_empty() {
+        buffer_writer.write_from_iter(values.by_ref()).unwrap();
+
+        buffer_reader.drain_available(|v| {
+            black_box(v);
+        });
+    }
+
+    tstart.elapsed()
+}
+
+fn two_threads(create: RingConstructor, capacity: usize) -> Duration {
+    let (mut buffer_writer, mut buffer_reader) = create(capacity);
+
+    let tstart = Instant::now();
+
+    let writer_thread = std::thread::spawn(move || {
+        for idx in 0..NUM_ELEMENTS {
+            let mut value = idx;
+
+            while let Err(e) = buffer_writer.try_write(value) {
+                value = e.into_inner();
+
+                std::thread::yield_now();
+            }
+        }
+    });
+
+    let mut received = 0;
+
+    while received < NUM_ELEMENTS {
+        match buffer_reader.try_read() {
+            Ok(v) => {
+                black_box(v);
+
+                received += 1;
+            }
+            Err(_) => std::thread::yield_now(),
+        }
+    }
+
+    writer_thread.join().unwrap();
+
+    tstart.elapsed()
+}
+
+fn ping_pong(create: RingConstructor, capacity: usize) -> Duration {
+    let (mut ping_writer, mut ping_reader) = create(capacity);
+    let (mut pong_writer, mut pong_reader) = create(capacity);
+
+    let num_round_trips = NUM_ROUND_TRIPS;
+
+    let tstart = Instant::now();
+
+    let echo_thread = std::thread::spawn(move || {
+        for _ in 0..num_round_trips {
+            let mut value = loop {
+                match ping_reader.try_read() {
+                    Ok(v) => break v,
+                    Err(_) => std::thread::yield_now(),
+                }
+            };
+
+            while let Err(e) = pong_writer.try_write(value) {
+                value = e.into_inner();
+
+                std::thread::yield_now();
+            }
+        }
+    });
+
+    for idx in 0..num_round_trips {
+        let mut value = idx;
+
+        while let Err(e) = ping_writer.try_write(value) {
+            value = e.into_inner();
+
+            std::thread::yield_now();
+        }
+
+        loop {
+            match pong_reader.try_read() {
+                Ok(v) => {
+                    assert_eq!(black_box(v), idx);
+
+                    break;
+                }
+                Err(_) => std::thread::yield_now(),
+            }
+        }
+    }
+
+    echo_thread.join().unwrap();
+
+    tstart.elapsed()
+}
+
+fn report(name: &str, duration: Duration) {
+    report_ops(name, NUM_ELEMENTS, "elem", duration);
+}
+
+fn report_ops(name: &str, num_ops: u64, unit: &str, duration: Duration) {
+    println!(
+        "{:<32} {:>8.2} M{}/s {:>8.2} ns/{}",
+        name,
+        num_ops as f64 / duration.as_secs_f64() / 1e6,
+        unit,
+        duration.as_nanos() as f64 / num_ops as f64,
+        unit
+    );
+}
+
+pub fn main() {
+    let capacity = 1000;
+
+    report(
+        "single thread, masked",
+        single_thread(create_ring_buffer, capacity),
+    );
+    report(
+        "single thread, compact",
+        single_thread(create_compact_ring_buffer, capacity),
+    );
+
+    report(
+        "single thread batched, masked",
+        single_thread_batched(create_ring_buffer, capacity),
+    );
+    report(
+        "single thread batched, compact",
+        single_thread_batched(create_compact_ring_buffer, capacity),
+    );
+
+    if std::thread::available_parallelism().map_or(1, |n| n.get()) < 2 {
+        println!("only one core available, the following cases don't run in parallel");
+    }
+
+    report(
+        "two threads, masked",
+        two_threads(create_ring_buffer, capacity),
+    );
+    report(
+        "two threads, compact",
+        two_threads(create_compact_ring_buffer, capacity),
+    );
+
+    report_ops(
+        "ping pong",
+        NUM_ROUND_TRIPS,
+        "rtt",
+        ping_pong(create_ring_buffer, capacity),
+    );
+}
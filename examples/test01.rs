@@ -8,38 +8,24 @@ pub fn main() {
 
     let producer_thread = std::thread::spawn(move || {
         for idx in 0..num_messages {
-            let msg = String::from(format!("Message {}", idx));
+            let msg = format!("Message {}", idx);
 
             let tstart = Instant::now();
 
-            let mut result = buffer_writer.try_write(msg);
+            buffer_writer
+                .write(msg)
+                .expect("Reader disconnected");
 
             let tend = Instant::now();
 
             println!("write took {} ns", tend.duration_since(tstart).as_nanos() as u64);
-
-            while result.is_err() {
-                std::thread::sleep(std::time::Duration::from_millis(10));
-
-                result = buffer_writer.try_write(result.err().unwrap());
-            }
         }
     });
 
     let consumer_thread = std::thread::spawn(move || {
-        let mut num_received_messages = 0;
-
-        while num_received_messages < num_messages {
-            let received_msg = buffer_reader.try_read();
-
-            match received_msg {
-                Some(v) => {
-                    println!("Received:  {}", v);
-
-                    num_received_messages += 1;
-                }
-                _ => std::thread::sleep(std::time::Duration::from_millis(10)),
-            }
+        // Ends once the producer thread dropped its writer and everything was read.
+        while let Ok(received_msg) = buffer_reader.read() {
+            println!("Received:  {}", received_msg);
         }
     });
 
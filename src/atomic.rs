@@ -0,0 +1,12 @@
+//! Atomics used by the rings. They are taken from `portable-atomic` if the feature of the same
+//! name is enabled, so the rings also work on targets without native 64-bit atomics.
+
+#[cfg(not(feature = "portable-atomic"))]
+#[cfg_attr(any(loom, not(feature = "alloc")), allow(unused_imports))]
+pub(crate) use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+
+#[cfg(feature = "portable-atomic")]
+#[cfg_attr(any(loom, not(feature = "alloc")), allow(unused_imports))]
+pub(crate) use portable_atomic::{AtomicBool, AtomicU64, AtomicUsize};
+
+pub(crate) use core::sync::atomic::Ordering;
@@ -0,0 +1,510 @@
+//! Bip buffer: a byte ring whose reservations and readable regions are always contiguous.
+//!
+//! Instead of wrapping a reservation around the end of the storage, the writer starts it at the
+//! beginning once there is room there and records the end of the data written so far as the
+//! watermark. The reader reads up to the watermark and then continues at the beginning as well.
+//! Some bytes at the end of the storage may stay unused for a lap, so a reservation of `n` bytes
+//! can fail even if more than `n` bytes are free in total.
+//!
+//! ```
+//! use atomic_ring_buffer::create_bip_buffer;
+//!
+//! let (mut buffer_writer, mut buffer_reader) = create_bip_buffer(16);
+//!
+//! let mut grant = buffer_writer.reserve(5).unwrap();
+//!
+//! grant.copy_from_slice(b"hello");
+//! grant.commit(5);
+//!
+//! let grant = buffer_reader.readable().unwrap();
+//!
+//! assert_eq!(&*grant, b"hello");
+//!
+//! grant.release(5);
+//! ```
+
+use alloc::sync::Arc;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use crate::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::cache_padded::CachePadded;
+use crate::error::{TryReadError, TryReserveError};
+use crate::{alloc_slots, dealloc_slots};
+
+/// Positions are offsets into the storage rather than free running indices.
+///
+/// While `wr_index >= rd_index` the readable bytes are `rd_index..wr_index`. Once the writer
+/// wrapped around, `wr_index < rd_index` and the readable bytes are `rd_index..watermark`
+/// followed by `0..wr_index`. The writer never catches up with `rd_index` from behind, so equal
+/// positions always mean the buffer is empty.
+///
+/// If the buffer is empty but a reservation doesn't fit in front of the end, the writer moves
+/// both positions back to the beginning. It publishes an empty wrap around, with the watermark
+/// at `rd_index`, and then moves `rd_index` to 0 itself instead of waiting for the reader to do
+/// so.
+struct BipBufferState {
+    capacity: usize,
+
+    wr_index: CachePadded<AtomicUsize>,
+    rd_index: CachePadded<AtomicUsize>,
+
+    // End of the readable bytes in front of the wrap, `capacity` while the writer hasn't
+    // wrapped around.
+    watermark: CachePadded<AtomicUsize>,
+
+    closed: AtomicBool,
+
+    // Zeroed when allocated, so every reservation hands out initialized bytes.
+    storage: NonNull<u8>,
+}
+
+/// Writing half of a bip buffer, see [`create_bip_buffer`].
+pub struct BipWriter {
+    shared_state: Arc<BipBufferState>,
+}
+
+/// Reading half of a bip buffer, see [`create_bip_buffer`].
+pub struct BipReader {
+    shared_state: Arc<BipBufferState>,
+}
+
+/// Contiguous bytes reserved by [`BipWriter::reserve`], dereferences to them.
+///
+/// Dropping the grant without committing discards it, nothing is published.
+pub struct BipWriteGrant<'a> {
+    writer: &'a mut BipWriter,
+    start: usize,
+    len: usize,
+}
+
+/// Contiguous readable bytes granted by [`BipReader::readable`], dereferences to them.
+///
+/// Dropping the grant without releasing leaves all bytes in the buffer.
+pub struct BipReadGrant<'a> {
+    reader: &'a mut BipReader,
+    start: usize,
+    len: usize,
+}
+
+impl BipBufferState {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    fn bytes(&self, start: usize, len: usize) -> *mut u8 {
+        debug_assert!(start + len <= self.capacity);
+
+        unsafe { self.storage.as_ptr().add(start) }
+    }
+}
+
+impl Drop for BipBufferState {
+    fn drop(&mut self) {
+        unsafe {
+            dealloc_slots(self.storage, self.capacity);
+        }
+    }
+}
+
+// The storage only holds bytes, each region is only accessed by one side at a time.
+unsafe impl Send for BipBufferState {}
+unsafe impl Sync for BipBufferState {}
+
+impl BipWriter {
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity
+    }
+
+    /// Returns true once the reader has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Reserves exactly `len` contiguous bytes, fails with [`TryReserveError::Full`] if there is
+    /// no such region right now.
+    pub fn reserve(&mut self, len: usize) -> Result<BipWriteGrant<'_>, TryReserveError> {
+        let state = self.shared_state.deref();
+
+        if state.is_closed() {
+            return Err(TryReserveError::Disconnected);
+        }
+
+        if len > state.capacity {
+            return Err(TryReserveError::TooLarge);
+        }
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+        let cur_read_idx = state.rd_index.load(Ordering::Acquire);
+
+        let start = if cur_write_idx < cur_read_idx {
+            // Wrapped around already, only the gap up to the reader is left.
+            if cur_read_idx - cur_write_idx <= len {
+                return Err(TryReserveError::Full);
+            }
+
+            cur_write_idx
+        } else if state.capacity - cur_write_idx >= len {
+            cur_write_idx
+        } else if cur_read_idx > len {
+            // Wraps around, the bytes from `cur_write_idx` to the end stay unused for a lap.
+            0
+        } else if cur_read_idx == cur_write_idx {
+            // Nothing to read, the whole storage is free once both positions are back at the
+            // beginning.
+            state.watermark.store(cur_write_idx, Ordering::Release);
+            state.wr_index.store(0, Ordering::Release);
+
+            // Fails if the reader followed the wrap around on its own in the meantime.
+            let _ = state.rd_index.compare_exchange(
+                cur_read_idx,
+                0,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+
+            0
+        } else {
+            return Err(TryReserveError::Full);
+        };
+
+        Ok(BipWriteGrant {
+            writer: self,
+            start,
+            len,
+        })
+    }
+}
+
+impl Drop for BipWriter {
+    fn drop(&mut self) {
+        self.shared_state.close();
+    }
+}
+
+impl BipWriteGrant<'_> {
+    /// Publishes the first `count` reserved bytes to the reader.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is larger than the grant.
+    pub fn commit(self, count: usize) {
+        assert!(count <= self.len, "committing more bytes than reserved");
+
+        let state = self.writer.shared_state.deref();
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+        let new_write_idx = self.start + count;
+
+        if self.start < cur_write_idx {
+            // The reader has to know where the bytes in front of the wrap end before it can
+            // see the new write index.
+            state.watermark.store(cur_write_idx, Ordering::Release);
+        } else if new_write_idx > state.watermark.load(Ordering::Relaxed) {
+            // The reader moved past the old watermark, the whole storage is usable again.
+            state.watermark.store(state.capacity, Ordering::Release);
+        }
+
+        state.wr_index.store(new_write_idx, Ordering::Release);
+    }
+}
+
+impl Deref for BipWriteGrant<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let state = self.writer.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts(state.bytes(self.start, self.len), self.len) }
+    }
+}
+
+impl DerefMut for BipWriteGrant<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let state = self.writer.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts_mut(state.bytes(self.start, self.len), self.len) }
+    }
+}
+
+impl BipReader {
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity
+    }
+
+    /// Returns true once the writer has been dropped. There may still be bytes left to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Grants access to the contiguous bytes that are readable right now. Bytes after the wrap
+    /// around are granted once the ones in front of it have been released.
+    pub fn readable(&mut self) -> Result<BipReadGrant<'_>, TryReadError> {
+        let (mut start, mut len) = self.readable_region();
+
+        if len == 0 {
+            if !self.shared_state.is_closed() {
+                return Err(TryReadError::Empty);
+            }
+
+            // The writer may have published more bytes right before closing.
+            (start, len) = self.readable_region();
+
+            if len == 0 {
+                return Err(TryReadError::Disconnected);
+            }
+        }
+
+        Ok(BipReadGrant {
+            reader: self,
+            start,
+            len,
+        })
+    }
+
+    fn readable_region(&mut self) -> (usize, usize) {
+        let state = self.shared_state.deref();
+
+        let (cur_write_idx, watermark, mut cur_read_idx) = loop {
+            let cur_read_idx = state.rd_index.load(Ordering::Acquire);
+            let cur_write_idx = state.wr_index.load(Ordering::Acquire);
+            let watermark = state.watermark.load(Ordering::Acquire);
+
+            // The positions only belong together if the writer didn't move the read index back
+            // to the beginning in between.
+            if state.rd_index.load(Ordering::Acquire) == cur_read_idx {
+                break (cur_write_idx, watermark, cur_read_idx);
+            }
+        };
+
+        if cur_write_idx < cur_read_idx && cur_read_idx == watermark {
+            // Everything in front of the wrap has been read, continue at the beginning.
+            cur_read_idx = 0;
+
+            state.rd_index.store(0, Ordering::Release);
+        }
+
+        let end = if cur_write_idx < cur_read_idx {
+            watermark
+        } else {
+            cur_write_idx
+        };
+
+        (cur_read_idx, end - cur_read_idx)
+    }
+}
+
+impl Drop for BipReader {
+    fn drop(&mut self) {
+        self.shared_state.close();
+    }
+}
+
+impl BipReadGrant<'_> {
+    /// Hands the first `count` granted bytes back to the writer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is larger than the grant.
+    pub fn release(self, count: usize) {
+        assert!(count <= self.len, "releasing more bytes than granted");
+
+        self.reader
+            .shared_state
+            .rd_index
+            .store(self.start + count, Ordering::Release);
+    }
+}
+
+impl Deref for BipReadGrant<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let state = self.reader.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts(state.bytes(self.start, self.len), self.len) }
+    }
+}
+
+/// Creates a bip buffer holding up to `buffer_capacity` bytes.
+pub fn create_bip_buffer(buffer_capacity: usize) -> (BipWriter, BipReader) {
+    let capacity = buffer_capacity.max(1);
+
+    let storage = alloc_slots::<u8>(capacity);
+
+    unsafe {
+        core::ptr::write_bytes(storage.as_ptr(), 0, capacity);
+    }
+
+    let shared_state = Arc::new(BipBufferState {
+        capacity,
+        wr_index: CachePadded(AtomicUsize::new(0)),
+        rd_index: CachePadded(AtomicUsize::new(0)),
+        watermark: CachePadded(AtomicUsize::new(capacity)),
+        closed: AtomicBool::new(false),
+        storage,
+    });
+
+    (
+        BipWriter {
+            shared_state: shared_state.clone(),
+        },
+        BipReader { shared_state },
+    )
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::create_bip_buffer;
+    use crate::{TryReadError, TryReserveError};
+
+    #[test]
+    fn bip_wrap_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_bip_buffer(10);
+
+        assert_eq!(
+            buffer_writer.reserve(11).err(),
+            Some(TryReserveError::TooLarge)
+        );
+
+        let mut grant = buffer_writer.reserve(8).unwrap();
+
+        grant.copy_from_slice(b"abcdefgh");
+        grant.commit(8);
+
+        let grant = buffer_reader.readable().unwrap();
+
+        assert_eq!(&*grant, b"abcdefgh");
+
+        grant.release(6);
+
+        // Only 2 bytes are left at the end, the reservation starts at the beginning instead.
+        let mut grant = buffer_writer.reserve(4).unwrap();
+
+        grant.copy_from_slice(b"ijkl");
+        grant.commit(3);
+
+        // The gap up to the reader has to stay open.
+        assert_eq!(buffer_writer.reserve(3).err(), Some(TryReserveError::Full));
+
+        assert_eq!(&*buffer_reader.readable().unwrap(), b"gh");
+
+        buffer_reader.readable().unwrap().release(2);
+
+        assert_eq!(&*buffer_reader.readable().unwrap(), b"ijk");
+
+        buffer_reader.readable().unwrap().release(3);
+
+        assert!(matches!(buffer_reader.readable(), Err(TryReadError::Empty)));
+
+        drop(buffer_writer);
+
+        assert!(matches!(
+            buffer_reader.readable(),
+            Err(TryReadError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn bip_empty_wrap_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_bip_buffer(10);
+
+        buffer_writer.reserve(5).unwrap().commit(5);
+        buffer_reader.readable().unwrap().release(5);
+
+        // Neither the 5 bytes at the end nor the ones in front of the reader are enough, but the
+        // buffer is empty.
+        let mut grant = buffer_writer.reserve(6).unwrap();
+
+        grant.copy_from_slice(b"abcdef");
+        grant.commit(6);
+
+        assert_eq!(&*buffer_reader.readable().unwrap(), b"abcdef");
+
+        buffer_reader.readable().unwrap().release(6);
+
+        assert!(buffer_writer.reserve(10).is_ok());
+    }
+
+    #[test]
+    fn bip_discard_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_bip_buffer(4);
+
+        buffer_writer.reserve(4).unwrap().copy_from_slice(b"lost");
+
+        assert!(matches!(buffer_reader.readable(), Err(TryReadError::Empty)));
+
+        buffer_writer.reserve(4).unwrap().commit(4);
+
+        assert_eq!(buffer_reader.readable().unwrap().len(), 4);
+        assert_eq!(buffer_writer.reserve(1).err(), Some(TryReserveError::Full));
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            buffer_writer.reserve(1).err(),
+            Some(TryReserveError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn bip_threaded_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_bip_buffer(100);
+
+        let num_chunks = if cfg!(miri) { 100 } else { 10_000 };
+
+        let writer_thread = std::thread::spawn(move || {
+            let mut next = 0u8;
+
+            for idx in 0..num_chunks {
+                let len = 1 + idx % 40;
+
+                let mut grant = loop {
+                    match buffer_writer.reserve(len) {
+                        Ok(grant) => break grant,
+                        Err(TryReserveError::Full) => std::thread::yield_now(),
+                        Err(e) => panic!("{}", e),
+                    }
+                };
+
+                for b in grant.iter_mut() {
+                    *b = next;
+                    next = next.wrapping_add(1);
+                }
+
+                grant.commit(len);
+            }
+        });
+
+        let mut expected = 0u8;
+        let mut received = 0;
+
+        loop {
+            match buffer_reader.readable() {
+                Ok(grant) => {
+                    for &b in grant.iter() {
+                        assert_eq!(b, expected);
+
+                        expected = expected.wrapping_add(1);
+                    }
+
+                    received += grant.len();
+
+                    let len = grant.len();
+
+                    grant.release(len);
+                }
+                Err(TryReadError::Empty) => std::thread::yield_now(),
+                Err(TryReadError::Disconnected) => break,
+            }
+        }
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(received, (0..num_chunks).map(|idx| 1 + idx % 40).sum());
+    }
+}
@@ -0,0 +1,560 @@
+//! Single producer ring buffer whose elements are seen by every reader.
+//!
+//! Every [`BroadcastReader`] keeps its own cursor into the shared slots and clones the elements
+//! it reads, so `T: Clone` is only required for reading. A slot is guarded by a small
+//! reader/writer lock, readers only hold it while cloning and the writer only while swapping in
+//! the new element. The element it replaces is dropped after the lock is released.
+//!
+//! The writer only ever replaces elements no reader needs any more or, in a lagging ring,
+//! elements it is allowed to take away from slow readers. Readers arriving while the writer
+//! wants the lock skip the element instead of holding the writer up, so the writer at most
+//! waits for the clones already in progress.
+//!
+//! By default the writer can't overwrite an element before every reader has seen it. Rings
+//! created by [`create_lagging_broadcast_ring_buffer`] let the writer overwrite old elements
+//! instead, readers that fall too far behind skip them and get
+//! [`TryBroadcastReadError::Lagged`].
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ops::Deref;
+
+use crate::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use crate::cache_padded::CachePadded;
+use crate::error::{TryBroadcastReadError, TryWriteError};
+
+/// Set in `Slot::lock` while the writer replaces the element or waits to do so.
+const SLOT_WRITING: usize = 1 << (usize::BITS - 1);
+
+struct Slot<T> {
+    // Number of readers cloning the element, plus `SLOT_WRITING`.
+    lock: AtomicUsize,
+
+    // Position and value of the element last written to the slot.
+    value: UnsafeCell<Option<(u64, T)>>,
+}
+
+struct BroadcastState<T: Sized> {
+    slot_count: u64,
+    slot_mask: u64,
+    masked: bool,
+
+    lagging: bool,
+
+    wr_index: CachePadded<AtomicU64>,
+
+    closed: AtomicBool,
+
+    // Cursors of all readers, only needed by the writer to find the slowest one.
+    cursors: CursorList,
+
+    slots: Box<[Slot<T>]>,
+}
+
+type Cursor = Arc<CachePadded<AtomicU64>>;
+
+/// List of reader cursors behind a spin lock, it is only locked to subscribe, to drop a reader
+/// and when the writer runs out of slots.
+struct CursorList {
+    locked: AtomicBool,
+    cursors: UnsafeCell<Vec<Cursor>>,
+}
+
+/// Writing half of a broadcast ring, see [`create_broadcast_ring_buffer`].
+pub struct BroadcastWriter<T: Sized> {
+    shared_state: Arc<BroadcastState<T>>,
+
+    // Cursor of the slowest reader when the cursors were last scanned. It only ever lags behind,
+    // so the cursors are only scanned again once the ring looks full.
+    cached_min_cursor: u64,
+}
+
+/// Reading half of a broadcast ring, created with the ring or by [`BroadcastWriter::subscribe`].
+pub struct BroadcastReader<T: Sized> {
+    shared_state: Arc<BroadcastState<T>>,
+
+    cursor: Cursor,
+}
+
+impl CursorList {
+    fn with<R, F: FnOnce(&mut Vec<Cursor>) -> R>(&self, f: F) -> R {
+        while self
+            .locked
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+
+        let ret = f(unsafe { &mut *self.cursors.get() });
+
+        self.locked.store(false, Ordering::Release);
+
+        ret
+    }
+}
+
+impl<T: Sized> BroadcastState<T> {
+    fn capacity(&self) -> usize {
+        self.slot_count as usize
+    }
+
+    fn slot(&self, index: u64) -> &Slot<T> {
+        let slot = if self.masked {
+            index & self.slot_mask
+        } else {
+            index % self.slot_count
+        };
+
+        &self.slots[slot as usize]
+    }
+
+    fn subscribe(self: &Arc<Self>) -> BroadcastReader<T> {
+        // Registering under the lock keeps the writer from missing the new cursor, which starts
+        // at the next element written.
+        let cursor = self.cursors.with(|cursors| {
+            let cursor = Arc::new(CachePadded(AtomicU64::new(
+                self.wr_index.load(Ordering::Acquire),
+            )));
+
+            cursors.push(cursor.clone());
+
+            cursor
+        });
+
+        BroadcastReader {
+            shared_state: self.clone(),
+            cursor,
+        }
+    }
+}
+
+// Elements are handed to readers as clones, while a reader clones an element other readers may
+// clone it as well.
+unsafe impl<T: Send + Sync> Send for BroadcastState<T> {}
+unsafe impl<T: Send + Sync> Sync for BroadcastState<T> {}
+
+impl<T: Sized> BroadcastWriter<T> {
+    /// Creates a new reader that sees every element written from now on.
+    pub fn subscribe(&self) -> BroadcastReader<T> {
+        self.shared_state.subscribe()
+    }
+
+    /// Number of readers.
+    pub fn reader_count(&self) -> usize {
+        self.shared_state.cursors.with(|cursors| cursors.len())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity()
+    }
+
+    /// Writes `value` for all readers. Fails with [`TryWriteError::Full`] if the slowest reader
+    /// hasn't seen the oldest element yet, unless the ring lets readers lag.
+    pub fn try_write(&mut self, value: T) -> Result<(), TryWriteError<T>> {
+        let state = self.shared_state.deref();
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+
+        if !state.lagging && cur_write_idx - self.cached_min_cursor >= state.slot_count {
+            self.cached_min_cursor = state.cursors.with(|cursors| {
+                cursors
+                    .iter()
+                    .map(|cursor| cursor.load(Ordering::Acquire))
+                    .min()
+                    .unwrap_or(cur_write_idx)
+            });
+
+            if cur_write_idx - self.cached_min_cursor >= state.slot_count {
+                return Err(TryWriteError::Full(value));
+            }
+        }
+
+        let slot = state.slot(cur_write_idx);
+
+        // No new readers get in once the flag is set, only the clones already in progress are
+        // waited for.
+        slot.lock.fetch_or(SLOT_WRITING, Ordering::Acquire);
+
+        while slot.lock.load(Ordering::Acquire) != SLOT_WRITING {
+            core::hint::spin_loop();
+        }
+
+        let replaced = unsafe { (*slot.value.get()).replace((cur_write_idx, value)) };
+
+        slot.lock.store(0, Ordering::Release);
+
+        state.wr_index.store(cur_write_idx + 1, Ordering::Release);
+
+        // The element written one lap earlier is only dropped now, a panic in its `Drop` leaves
+        // the ring intact.
+        drop(replaced);
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for BroadcastWriter<T> {
+    fn drop(&mut self) {
+        self.shared_state.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<T: Sized> BroadcastReader<T> {
+    /// Number of elements this reader hasn't seen yet.
+    pub fn size(&self) -> usize {
+        let cur_write_idx = self.shared_state.wr_index.load(Ordering::Acquire);
+
+        cur_write_idx.wrapping_sub(self.cursor.load(Ordering::Relaxed)) as usize
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity()
+    }
+
+    /// Returns true once the writer has been dropped. There may still be elements left to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.closed.load(Ordering::Acquire)
+    }
+
+    /// Creates another reader that sees every element written from now on.
+    pub fn subscribe(&self) -> BroadcastReader<T> {
+        self.shared_state.subscribe()
+    }
+}
+
+impl<T: Clone> BroadcastReader<T> {
+    /// Returns a clone of the next element this reader hasn't seen yet.
+    pub fn try_read(&mut self) -> Result<T, TryBroadcastReadError> {
+        let state = self.shared_state.deref();
+
+        let cur_read_idx = self.cursor.load(Ordering::Relaxed);
+        let mut cur_write_idx = state.wr_index.load(Ordering::Acquire);
+
+        if cur_read_idx == cur_write_idx {
+            if !self.is_closed() {
+                return Err(TryBroadcastReadError::Empty);
+            }
+
+            // The writer may have written more elements right before it was dropped.
+            cur_write_idx = state.wr_index.load(Ordering::Acquire);
+
+            if cur_read_idx == cur_write_idx {
+                return Err(TryBroadcastReadError::Disconnected);
+            }
+        }
+
+        if cur_write_idx - cur_read_idx <= state.slot_count {
+            if let Some(v) = self.read_slot(cur_read_idx) {
+                self.cursor.store(cur_read_idx + 1, Ordering::Release);
+
+                return Ok(v);
+            }
+        }
+
+        // The writer overwrote the element or is about to, skip to the oldest one still in the
+        // ring.
+        let oldest_idx =
+            (state.wr_index.load(Ordering::Acquire) - state.slot_count).max(cur_read_idx + 1);
+
+        self.cursor.store(oldest_idx, Ordering::Release);
+
+        Err(TryBroadcastReadError::Lagged(oldest_idx - cur_read_idx))
+    }
+
+    /// Clones the element at `index` unless it has been overwritten or the writer is about to
+    /// overwrite it.
+    fn read_slot(&self, index: u64) -> Option<T> {
+        // Lets the writer in again even if cloning panics.
+        struct ReadLock<'a>(&'a AtomicUsize);
+
+        impl Drop for ReadLock<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_sub(1, Ordering::Release);
+            }
+        }
+
+        let slot = self.shared_state.slot(index);
+
+        let mut readers = slot.lock.load(Ordering::Relaxed);
+
+        loop {
+            // The writer only takes the slot for an element readers don't need or may miss.
+            if readers & SLOT_WRITING != 0 {
+                return None;
+            }
+
+            match slot.lock.compare_exchange_weak(
+                readers,
+                readers + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(v) => readers = v,
+            }
+        }
+
+        let _lock = ReadLock(&slot.lock);
+
+        match unsafe { &*slot.value.get() } {
+            Some((written_idx, v)) if *written_idx == index => Some(v.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Yields elements until the reader has seen all of them, skipping lagged ones.
+impl<T: Clone> Iterator for BroadcastReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            match self.try_read() {
+                Ok(v) => return Some(v),
+                Err(TryBroadcastReadError::Lagged(_)) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+impl<T> Drop for BroadcastReader<T> {
+    fn drop(&mut self) {
+        let cursor = &self.cursor;
+
+        self.shared_state
+            .cursors
+            .with(|cursors| cursors.retain(|c| !Arc::ptr_eq(c, cursor)));
+    }
+}
+
+/// Creates a broadcast ring buffer holding up to `buffer_capacity` elements. The writer can't
+/// overwrite an element before all readers have seen it.
+pub fn create_broadcast_ring_buffer<T: Sized>(
+    buffer_capacity: usize,
+) -> (BroadcastWriter<T>, BroadcastReader<T>) {
+    new_broadcast_ring_buffer(buffer_capacity, false)
+}
+
+/// Creates a broadcast ring buffer holding up to `buffer_capacity` elements in which the writer
+/// doesn't wait for slow readers. Readers that fall behind by more than the capacity miss
+/// elements, the writer only waits for readers in the middle of cloning the element it replaces.
+pub fn create_lagging_broadcast_ring_buffer<T: Sized>(
+    buffer_capacity: usize,
+) -> (BroadcastWriter<T>, BroadcastReader<T>) {
+    new_broadcast_ring_buffer(buffer_capacity, true)
+}
+
+fn new_broadcast_ring_buffer<T: Sized>(
+    buffer_capacity: usize,
+    lagging: bool,
+) -> (BroadcastWriter<T>, BroadcastReader<T>) {
+    let slot_count = buffer_capacity.max(1);
+
+    let slots = (0..slot_count)
+        .map(|_| Slot {
+            lock: AtomicUsize::new(0),
+            value: UnsafeCell::new(None),
+        })
+        .collect();
+
+    let shared_state = Arc::new(BroadcastState {
+        slot_count: slot_count as u64,
+        slot_mask: (slot_count as u64).wrapping_sub(1),
+        masked: slot_count.is_power_of_two(),
+        lagging,
+        wr_index: CachePadded(AtomicU64::new(0)),
+        closed: AtomicBool::new(false),
+        cursors: CursorList {
+            locked: AtomicBool::new(false),
+            cursors: UnsafeCell::new(Vec::new()),
+        },
+        slots,
+    });
+
+    let buffer_reader = shared_state.subscribe();
+
+    (
+        BroadcastWriter {
+            shared_state,
+            cached_min_cursor: 0,
+        },
+        buffer_reader,
+    )
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::{create_broadcast_ring_buffer, create_lagging_broadcast_ring_buffer};
+    use crate::{TryBroadcastReadError, TryWriteError};
+
+    #[test]
+    fn broadcast_test() {
+        let (mut buffer_writer, mut first_reader) = create_broadcast_ring_buffer::<String>(2);
+
+        assert!(buffer_writer.try_write(String::from("a")).is_ok());
+
+        let mut second_reader = buffer_writer.subscribe();
+
+        assert_eq!(buffer_writer.reader_count(), 2);
+
+        assert!(buffer_writer.try_write(String::from("b")).is_ok());
+        assert!(buffer_writer.try_write(String::from("c")).is_err());
+
+        assert_eq!(first_reader.try_read().as_deref(), Ok("a"));
+        assert!(buffer_writer.try_write(String::from("c")).is_ok());
+
+        // The second reader only holds up the writer once it falls behind.
+        assert_eq!(
+            buffer_writer.try_write(String::from("d")),
+            Err(TryWriteError::Full(String::from("d")))
+        );
+        assert_eq!(second_reader.by_ref().collect::<Vec<_>>(), vec!["b", "c"]);
+        assert_eq!(
+            buffer_writer.try_write(String::from("d")),
+            Err(TryWriteError::Full(String::from("d")))
+        );
+
+        drop(first_reader);
+
+        assert!(buffer_writer.try_write(String::from("d")).is_ok());
+
+        drop(buffer_writer);
+
+        assert_eq!(second_reader.try_read().as_deref(), Ok("d"));
+        assert_eq!(
+            second_reader.try_read(),
+            Err(TryBroadcastReadError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn lagging_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_lagging_broadcast_ring_buffer::<u32>(3);
+
+        for idx in 0..5 {
+            assert!(buffer_writer.try_write(idx).is_ok());
+        }
+
+        assert_eq!(
+            buffer_reader.try_read(),
+            Err(TryBroadcastReadError::Lagged(2))
+        );
+        assert_eq!(buffer_reader.try_read(), Ok(2));
+
+        assert!(buffer_writer.try_write(5).is_ok());
+
+        assert_eq!(buffer_reader.by_ref().collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(buffer_reader.try_read(), Err(TryBroadcastReadError::Empty));
+    }
+
+    struct Fragile {
+        v: u32,
+        armed: bool,
+    }
+
+    // Cloning 0 panics, dropping the original 1 panics.
+    impl Clone for Fragile {
+        fn clone(&self) -> Self {
+            assert_ne!(self.v, 0);
+
+            Fragile {
+                v: self.v,
+                armed: false,
+            }
+        }
+    }
+
+    impl Drop for Fragile {
+        fn drop(&mut self) {
+            if self.armed && self.v == 1 {
+                self.armed = false;
+
+                panic!("dropping 1");
+            }
+        }
+    }
+
+    #[test]
+    fn panic_test() {
+        let (mut buffer_writer, mut buffer_reader) =
+            create_lagging_broadcast_ring_buffer::<Fragile>(1);
+
+        let fragile = |v| Fragile { v, armed: true };
+
+        assert!(buffer_writer.try_write(fragile(0)).is_ok());
+        assert!(catch_unwind(AssertUnwindSafe(|| buffer_reader.try_read())).is_err());
+
+        // Neither the panicking clone nor the panicking drop keeps the slot locked.
+        assert!(buffer_writer.try_write(fragile(1)).is_ok());
+        assert!(catch_unwind(AssertUnwindSafe(|| buffer_writer.try_write(fragile(2)))).is_err());
+
+        assert_eq!(
+            buffer_reader.try_read().err(),
+            Some(TryBroadcastReadError::Lagged(2))
+        );
+        assert_eq!(buffer_reader.try_read().map(|v| v.v), Ok(2));
+
+        assert!(buffer_writer.try_write(fragile(3)).is_ok());
+        assert_eq!(buffer_reader.try_read().map(|v| v.v), Ok(3));
+    }
+
+    #[test]
+    fn broadcast_threaded_test() {
+        let (mut buffer_writer, buffer_reader) = create_broadcast_ring_buffer::<u64>(8);
+
+        let num_elements = if cfg!(miri) { 100 } else { 10_000 };
+
+        let mut buffer_readers = (0..3)
+            .map(|_| buffer_reader.subscribe())
+            .collect::<Vec<_>>();
+
+        buffer_readers.push(buffer_reader);
+
+        let reader_threads = buffer_readers
+            .into_iter()
+            .map(|mut buffer_reader| {
+                std::thread::spawn(move || {
+                    let mut expected = 0;
+
+                    loop {
+                        match buffer_reader.try_read() {
+                            Ok(v) => {
+                                assert_eq!(v, expected);
+
+                                expected += 1;
+                            }
+                            Err(TryBroadcastReadError::Empty) => std::thread::yield_now(),
+                            Err(e) => {
+                                assert_eq!(e, TryBroadcastReadError::Disconnected);
+
+                                return expected;
+                            }
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for idx in 0..num_elements {
+            let mut value = idx;
+
+            while let Err(e) = buffer_writer.try_write(value) {
+                value = e.into_inner();
+
+                std::thread::yield_now();
+            }
+        }
+
+        drop(buffer_writer);
+
+        for reader_thread in reader_threads {
+            assert_eq!(reader_thread.join().unwrap(), num_elements);
+        }
+    }
+}
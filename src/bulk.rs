@@ -0,0 +1,231 @@
+//! Batched writes and reads.
+//!
+//! Every operation here loads the peer's index once, moves as many elements as possible and
+//! publishes its own index with a single store at the end of the batch.
+
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+use crate::{BufferReader, BufferWriter, DisconnectedError, SharedBufferState};
+
+/// Cursor over the slots of one batch. The index is published when the cursor is dropped, so the
+/// elements already moved stay accounted for even if a user callback panics halfway through.
+pub(crate) struct BatchCursor<'a, T: Sized> {
+    state: &'a SharedBufferState<T>,
+    index: u64,
+    count: usize,
+    publish: fn(&SharedBufferState<T>, u64),
+}
+
+impl<'a, T: Sized> BatchCursor<'a, T> {
+    pub(crate) fn new(
+        state: &'a SharedBufferState<T>,
+        index: u64,
+        publish: fn(&SharedBufferState<T>, u64),
+    ) -> Self {
+        BatchCursor {
+            state,
+            index,
+            count: 0,
+            publish,
+        }
+    }
+
+    pub(crate) fn next_slot(&mut self) -> *mut T {
+        let slot = self.state.slot_ptr(self.index);
+
+        self.index += 1;
+        self.count += 1;
+
+        slot
+    }
+}
+
+impl<T: Sized> Drop for BatchCursor<'_, T> {
+    fn drop(&mut self) {
+        if self.count > 0 {
+            (self.publish)(self.state, self.index);
+        }
+    }
+}
+
+impl<T: Sized> BufferWriter<T> {
+    /// Writes elements taken from `iter` until either the ring is full or the iterator is
+    /// exhausted and returns how many were written.
+    ///
+    /// Only as many elements as fit into the ring are pulled from the iterator, pass
+    /// `iter.by_ref()` to keep the remaining ones. Fails without pulling any element once the
+    /// ring is closed.
+    pub fn write_from_iter<I: IntoIterator<Item = T>>(
+        &mut self,
+        iter: I,
+    ) -> Result<usize, DisconnectedError> {
+        if self.shared_state.is_closed() {
+            return Err(DisconnectedError);
+        }
+
+        let (cur_write_idx, free_slots) = self.writable_slots(usize::MAX);
+
+        let state = self.shared_state.deref();
+
+        let mut cursor = BatchCursor::new(state, cur_write_idx, SharedBufferState::publish_write);
+
+        for value in iter.into_iter().take(free_slots) {
+            unsafe {
+                core::ptr::write(cursor.next_slot(), value);
+            }
+        }
+
+        Ok(cursor.count)
+    }
+}
+
+impl<T: Sized + Copy> BufferWriter<T> {
+    /// Copies as many elements from `values` as fit into the ring and returns how many were
+    /// written. Fails once the ring is closed.
+    pub fn write_slice(&mut self, values: &[T]) -> Result<usize, DisconnectedError> {
+        if self.shared_state.is_closed() {
+            return Err(DisconnectedError);
+        }
+
+        let (cur_write_idx, free_slots) = self.writable_slots(values.len());
+
+        let count = values.len().min(free_slots);
+
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let state = self.shared_state.deref();
+
+        // The free region may wrap around the end of the storage, copy it in up to two parts.
+        let (first, first_len, second, second_len) = state.slot_ranges(cur_write_idx, count);
+
+        unsafe {
+            core::ptr::copy_nonoverlapping(values.as_ptr(), first, first_len);
+            core::ptr::copy_nonoverlapping(values.as_ptr().add(first_len), second, second_len);
+        }
+
+        state.publish_write(cur_write_idx + count as u64);
+
+        Ok(count)
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Moves readable elements into `buffer`, overwriting (and dropping) its previous contents,
+    /// and returns how many elements were read.
+    pub fn read_into(&mut self, buffer: &mut [T]) -> usize {
+        let max = buffer.len();
+        let mut dst = buffer.iter_mut();
+
+        self.drain_available_max(max, |v| {
+            if let Some(d) = dst.next() {
+                *d = v;
+            }
+        })
+    }
+
+    /// Reads up to `max` elements into a newly allocated vector.
+    pub fn read_to_vec(&mut self, max: usize) -> Vec<T> {
+        let mut ret = Vec::with_capacity(max.min(self.size()));
+
+        self.drain_available_max(max, |v| ret.push(v));
+
+        ret
+    }
+
+    /// Passes every element that is readable right now to `f` and returns how many were read.
+    pub fn drain_available<F: FnMut(T)>(&mut self, f: F) -> usize {
+        self.drain_available_max(usize::MAX, f)
+    }
+
+    fn drain_available_max<F: FnMut(T)>(&mut self, max: usize, mut f: F) -> usize {
+        let (cur_read_idx, available) = self.readable_slots(max);
+
+        let count = available.min(max);
+
+        let state = self.shared_state.deref();
+
+        let mut cursor = BatchCursor::new(state, cur_read_idx, SharedBufferState::publish_read);
+
+        for _ in 0..count {
+            let v = unsafe { core::ptr::read(cursor.next_slot()) };
+
+            f(v);
+        }
+
+        cursor.count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_ring_buffer, DisconnectedError};
+
+    #[test]
+    fn write_from_iter_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(7);
+
+        let mut values = 0..10u32;
+
+        assert_eq!(buffer_writer.write_from_iter(values.by_ref()), Ok(7));
+        assert_eq!(values.next(), Some(7));
+
+        assert_eq!(buffer_writer.size(), 7);
+        assert_eq!(buffer_writer.write_from_iter(values.by_ref()), Ok(0));
+
+        assert_eq!(buffer_reader.read_to_vec(3), vec![0, 1, 2]);
+        assert_eq!(buffer_writer.write_from_iter(values), Ok(2));
+
+        assert_eq!(
+            buffer_reader.read_to_vec(usize::MAX),
+            vec![3, 4, 5, 6, 8, 9]
+        );
+    }
+
+    #[test]
+    fn write_slice_wrap_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u16>(4);
+
+        assert_eq!(buffer_writer.write_slice(&[1, 2, 3]), Ok(3));
+        assert_eq!(buffer_reader.read_to_vec(3), vec![1, 2, 3]);
+
+        // Wraps around the end of the storage.
+        assert_eq!(buffer_writer.write_slice(&[4, 5, 6, 7, 8]), Ok(4));
+
+        let mut buffer = [0u16; 8];
+
+        assert_eq!(buffer_reader.read_into(&mut buffer), 4);
+        assert_eq!(buffer[..4], [4, 5, 6, 7]);
+        assert_eq!(buffer_reader.read_into(&mut buffer), 0);
+
+        drop(buffer_reader);
+
+        assert_eq!(buffer_writer.write_slice(&[9]), Err(DisconnectedError));
+    }
+
+    #[test]
+    fn drain_available_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<String>(4);
+
+        assert_eq!(
+            buffer_writer.write_from_iter(["a", "b", "c"].map(String::from)),
+            Ok(3)
+        );
+
+        let mut received = String::new();
+
+        assert_eq!(buffer_reader.drain_available(|s| received.push_str(&s)), 3);
+        assert_eq!(received, "abc");
+
+        assert!(buffer_reader.try_read().is_err());
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            buffer_writer.write_from_iter([String::from("d")]),
+            Err(DisconnectedError)
+        );
+    }
+}
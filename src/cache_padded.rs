@@ -0,0 +1,18 @@
+use core::ops::Deref;
+
+/// Aligns (and thereby pads) the wrapped value to the size of a cache line so that values
+/// written by different cores don't share a line.
+///
+/// x86_64 and aarch64 prefetch cache lines in pairs, so 128 bytes are used there.
+#[cfg_attr(any(target_arch = "x86_64", target_arch = "aarch64"), repr(align(128)))]
+#[cfg_attr(not(any(target_arch = "x86_64", target_arch = "aarch64")), repr(align(64)))]
+#[repr(C)]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
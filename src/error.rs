@@ -0,0 +1,226 @@
+//! Error types returned by the ring buffer handles.
+//!
+//! Errors of write operations hand the rejected value back to the caller.
+
+use core::error::Error;
+use core::fmt;
+
+/// Error returned by [`BufferWriter::try_write`](crate::BufferWriter::try_write).
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TryWriteError<T> {
+    /// The ring is full, the value could not be written right now.
+    Full(T),
+    /// The reader has been dropped or one side closed the ring.
+    Disconnected(T),
+}
+
+impl<T> TryWriteError<T> {
+    /// Returns the value that could not be written.
+    pub fn into_inner(self) -> T {
+        match self {
+            TryWriteError::Full(v) => v,
+            TryWriteError::Disconnected(v) => v,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        matches!(self, TryWriteError::Full(_))
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        matches!(self, TryWriteError::Disconnected(_))
+    }
+}
+
+impl<T> fmt::Debug for TryWriteError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryWriteError::Full(_) => f.write_str("Full(..)"),
+            TryWriteError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for TryWriteError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryWriteError::Full(_) => f.write_str("writing to a full ring buffer"),
+            TryWriteError::Disconnected(_) => f.write_str("writing to a disconnected ring buffer"),
+        }
+    }
+}
+
+impl<T> Error for TryWriteError<T> {}
+
+/// Error returned by [`BufferReader::try_read`](crate::BufferReader::try_read).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryReadError {
+    /// The ring is empty but the writer may still write into it.
+    Empty,
+    /// The ring is empty and the writer has been dropped or one side closed the ring.
+    Disconnected,
+}
+
+impl fmt::Display for TryReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReadError::Empty => f.write_str("reading from an empty ring buffer"),
+            TryReadError::Disconnected => {
+                f.write_str("reading from an empty and disconnected ring buffer")
+            }
+        }
+    }
+}
+
+impl Error for TryReadError {}
+
+/// Error returned by the blocking [`BufferWriter::write`](crate::BufferWriter::write) once the
+/// ring is disconnected. Contains the value that could not be written.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct WriteError<T>(pub T);
+
+impl<T> fmt::Debug for WriteError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("WriteError(..)")
+    }
+}
+
+impl<T> fmt::Display for WriteError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("writing to a disconnected ring buffer")
+    }
+}
+
+impl<T> Error for WriteError<T> {}
+
+/// Error returned by blocking reads once the ring is empty and disconnected.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct DisconnectedError;
+
+impl fmt::Display for DisconnectedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ring buffer is disconnected")
+    }
+}
+
+impl Error for DisconnectedError {}
+
+/// Error returned by [`BufferWriter::write_timeout`](crate::BufferWriter::write_timeout) and
+/// [`BufferWriter::write_deadline`](crate::BufferWriter::write_deadline).
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum WriteTimeoutError<T> {
+    /// There was no space in the ring before the deadline.
+    Timeout(T),
+    /// The reader has been dropped or one side closed the ring.
+    Disconnected(T),
+}
+
+impl<T> WriteTimeoutError<T> {
+    /// Returns the value that could not be written.
+    pub fn into_inner(self) -> T {
+        match self {
+            WriteTimeoutError::Timeout(v) => v,
+            WriteTimeoutError::Disconnected(v) => v,
+        }
+    }
+}
+
+impl<T> fmt::Debug for WriteTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteTimeoutError::Timeout(_) => f.write_str("Timeout(..)"),
+            WriteTimeoutError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> fmt::Display for WriteTimeoutError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WriteTimeoutError::Timeout(_) => {
+                f.write_str("timed out waiting for space in the ring buffer")
+            }
+            WriteTimeoutError::Disconnected(_) => {
+                f.write_str("writing to a disconnected ring buffer")
+            }
+        }
+    }
+}
+
+impl<T> Error for WriteTimeoutError<T> {}
+
+/// Error returned by [`BufferReader::read_timeout`](crate::BufferReader::read_timeout) and
+/// [`BufferReader::read_deadline`](crate::BufferReader::read_deadline).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ReadTimeoutError {
+    /// Nothing was written into the ring before the deadline.
+    Timeout,
+    /// The ring is empty and disconnected.
+    Disconnected,
+}
+
+impl fmt::Display for ReadTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadTimeoutError::Timeout => f.write_str("timed out waiting on an empty ring buffer"),
+            ReadTimeoutError::Disconnected => {
+                f.write_str("reading from an empty and disconnected ring buffer")
+            }
+        }
+    }
+}
+
+impl Error for ReadTimeoutError {}
+
+/// Error returned by [`BroadcastReader::try_read`](crate::BroadcastReader::try_read).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryBroadcastReadError {
+    /// The reader has seen every element but the writer may still write more.
+    Empty,
+    /// The writer overwrote the given number of elements before the reader got to them. The
+    /// reader continues with the oldest element still in the ring.
+    Lagged(u64),
+    /// The reader has seen every element and the writer has been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for TryBroadcastReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryBroadcastReadError::Empty => f.write_str("reading from an empty ring buffer"),
+            TryBroadcastReadError::Lagged(n) => write!(f, "reader lagged behind by {} elements", n),
+            TryBroadcastReadError::Disconnected => {
+                f.write_str("reading from an empty and disconnected ring buffer")
+            }
+        }
+    }
+}
+
+impl Error for TryBroadcastReadError {}
+
+/// Error returned when reserving contiguous space, by
+/// [`RecordWriter::reserve_record`](crate::RecordWriter::reserve_record) and
+/// [`BipWriter::reserve`](crate::BipWriter::reserve) among others.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TryReserveError {
+    /// There is not enough contiguous space right now.
+    Full,
+    /// The requested space is larger than the ring can ever provide in one piece.
+    TooLarge,
+    /// The reader has been dropped.
+    Disconnected,
+}
+
+impl fmt::Display for TryReserveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryReserveError::Full => f.write_str("writing to a full ring buffer"),
+            TryReserveError::TooLarge => {
+                f.write_str("reserving more than the ring buffer can hold")
+            }
+            TryReserveError::Disconnected => f.write_str("writing to a disconnected ring buffer"),
+        }
+    }
+}
+
+impl Error for TryReserveError {}
@@ -0,0 +1,580 @@
+//! `async` support for the ring buffer handles.
+//!
+//! [`BufferWriter`] implements `Sink` and [`BufferReader`] implements `Stream`. The plain
+//! handles keep their blocking `write` and `read`, so [`BufferWriter::into_async`] and
+//! [`BufferReader::into_async`] turn them into [`AsyncBufferWriter`] and [`AsyncBufferReader`],
+//! whose `write(value).await` and `read().await` wait instead of blocking the thread. These
+//! implement `Sink` and `Stream` as well.
+//!
+//! [`BufferReader`] is also an `Iterator`, so with `StreamExt` in scope `next` and `collect`
+//! are ambiguous on it. Call them as `StreamExt::next(&mut reader)` or use the
+//! [`AsyncBufferReader`], which is only a `Stream`.
+//!
+//! A write future dropped before it completed leaves its value with the [`AsyncBufferWriter`],
+//! which writes it ahead of the next value or when flushed. Values are neither lost nor written
+//! twice when a write is cancelled.
+//!
+//! Tasks register their waker in the shared state when the ring is full (writer) or empty
+//! (reader) and get woken by the peer's next index store, just like the blocking calls park
+//! the calling thread.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+
+use crate::{
+    BufferReader, BufferWriter, DisconnectedError, TryReadError, TryWriteError, WriteError,
+};
+
+/// Writing half of a ring buffer for use from `async` code, see [`BufferWriter::into_async`].
+pub struct AsyncBufferWriter<T: Sized> {
+    writer: BufferWriter<T>,
+
+    // Values of write futures dropped before the ring had room for them, oldest first.
+    pending: VecDeque<T>,
+}
+
+/// Reading half of a ring buffer for use from `async` code, see [`BufferReader::into_async`].
+pub struct AsyncBufferReader<T: Sized> {
+    reader: BufferReader<T>,
+}
+
+impl<T: Sized> AsyncBufferWriter<T> {
+    /// Writes `value` into the buffer, waiting asynchronously until there is space for it.
+    ///
+    /// Values of write futures dropped earlier are written first.
+    ///
+    /// The returned future is cancellation safe: dropping it before it completed keeps the value
+    /// in the writer, which writes it ahead of the next value or when flushed.
+    /// [`WriteFuture::into_inner`] takes the value back instead. If the ring gets disconnected
+    /// the value is handed back in the error.
+    pub fn write(&mut self, value: T) -> WriteFuture<'_, T> {
+        WriteFuture {
+            writer: self,
+            value: Some(value),
+        }
+    }
+
+    /// Number of values left behind by dropped write futures that are not in the ring yet.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn get_ref(&self) -> &BufferWriter<T> {
+        &self.writer
+    }
+
+    /// Gives access to the non-blocking methods of the writer, like
+    /// [`BufferWriter::try_write`]. These don't wait for the values of dropped write futures,
+    /// flush the writer first to keep the order.
+    pub fn get_mut(&mut self) -> &mut BufferWriter<T> {
+        &mut self.writer
+    }
+
+    /// Returns the plain writer. Values of dropped write futures that are not in the ring yet
+    /// are dropped, flush the writer first to keep them.
+    pub fn into_inner(self) -> BufferWriter<T> {
+        self.writer
+    }
+
+    /// Writes the values of dropped write futures. They are dropped if the ring got
+    /// disconnected.
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DisconnectedError>> {
+        while let Some(value) = self.pending.pop_front() {
+            match self.writer.poll_write(cx, value) {
+                Ok(()) => {}
+                Err(TryWriteError::Full(v)) => {
+                    self.pending.push_front(v);
+
+                    return Poll::Pending;
+                }
+                Err(TryWriteError::Disconnected(_)) => {
+                    self.pending.clear();
+
+                    return Poll::Ready(Err(DisconnectedError));
+                }
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+// The pending values are never pinned, they are moved into the ring by value.
+impl<T: Sized> Unpin for AsyncBufferWriter<T> {}
+
+impl<T: Sized> AsyncBufferReader<T> {
+    /// Reads the next element, waiting asynchronously until one is available.
+    /// Fails once the ring is empty and disconnected.
+    pub fn read(&mut self) -> ReadFuture<'_, T> {
+        ReadFuture {
+            reader: &mut self.reader,
+        }
+    }
+
+    pub fn get_ref(&self) -> &BufferReader<T> {
+        &self.reader
+    }
+
+    /// Gives access to the non-blocking methods of the reader, like
+    /// [`BufferReader::try_read`].
+    pub fn get_mut(&mut self) -> &mut BufferReader<T> {
+        &mut self.reader
+    }
+
+    pub fn into_inner(self) -> BufferReader<T> {
+        self.reader
+    }
+}
+
+impl<T: Sized> BufferWriter<T> {
+    /// Turns the writer into one whose `write` can be `.await`ed.
+    pub fn into_async(self) -> AsyncBufferWriter<T> {
+        AsyncBufferWriter {
+            writer: self,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, value: T) -> Result<(), TryWriteError<T>> {
+        let value = match self.try_write(value) {
+            Err(TryWriteError::Full(v)) => v,
+            result => return result,
+        };
+
+        self.shared_state.wr_waiter.register_waker(cx.waker());
+
+        let result = self.try_write(value);
+
+        if !matches!(result, Err(TryWriteError::Full(_))) {
+            self.shared_state.wr_waiter.cancel_wait();
+        }
+
+        result
+    }
+
+    fn poll_space(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), DisconnectedError>> {
+        let state = &self.shared_state;
+
+        if state.is_closed() {
+            return Poll::Ready(Err(DisconnectedError));
+        }
+
+        if !state.is_full() {
+            return Poll::Ready(Ok(()));
+        }
+
+        state.wr_waiter.register_waker(cx.waker());
+
+        if state.is_closed() {
+            state.wr_waiter.cancel_wait();
+
+            return Poll::Ready(Err(DisconnectedError));
+        }
+
+        if !state.is_full() {
+            state.wr_waiter.cancel_wait();
+
+            return Poll::Ready(Ok(()));
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Turns the reader into one whose `read` can be `.await`ed.
+    pub fn into_async(self) -> AsyncBufferReader<T> {
+        AsyncBufferReader { reader: self }
+    }
+
+    fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, DisconnectedError>> {
+        match self.try_read() {
+            Ok(v) => return Poll::Ready(Ok(v)),
+            Err(TryReadError::Disconnected) => return Poll::Ready(Err(DisconnectedError)),
+            Err(TryReadError::Empty) => {}
+        }
+
+        self.shared_state.rd_waiter.register_waker(cx.waker());
+
+        let result = match self.try_read() {
+            Ok(v) => Ok(v),
+            Err(TryReadError::Disconnected) => Err(DisconnectedError),
+            Err(TryReadError::Empty) => return Poll::Pending,
+        };
+
+        self.shared_state.rd_waiter.cancel_wait();
+
+        Poll::Ready(result)
+    }
+}
+
+/// Future returned by [`AsyncBufferWriter::write`].
+///
+/// Dropping it before it completed leaves the value with the writer, take the value back with
+/// [`WriteFuture::into_inner`] instead to not write it at all.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct WriteFuture<'a, T: Sized> {
+    writer: &'a mut AsyncBufferWriter<T>,
+    value: Option<T>,
+}
+
+impl<T: Sized> WriteFuture<'_, T> {
+    /// Takes back the value if it has not been written yet.
+    pub fn into_inner(mut self) -> Option<T> {
+        self.value.take()
+    }
+}
+
+// The value is never pinned, it is moved in and out of the ring by value.
+impl<T: Sized> Unpin for WriteFuture<'_, T> {}
+
+impl<T: Sized> Future for WriteFuture<'_, T> {
+    type Output = Result<(), WriteError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+
+        let value = this
+            .value
+            .take()
+            .expect("WriteFuture polled after completion");
+
+        match this.writer.poll_pending(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(DisconnectedError)) => return Poll::Ready(Err(WriteError(value))),
+            Poll::Pending => {
+                this.value = Some(value);
+
+                return Poll::Pending;
+            }
+        }
+
+        match this.writer.writer.poll_write(cx, value) {
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(TryWriteError::Disconnected(v)) => Poll::Ready(Err(WriteError(v))),
+            Err(TryWriteError::Full(v)) => {
+                this.value = Some(v);
+
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T: Sized> Drop for WriteFuture<'_, T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.value.take() {
+            self.writer.writer.shared_state.wr_waiter.cancel_wait();
+
+            self.writer.pending.push_back(value);
+        }
+    }
+}
+
+/// Future returned by [`AsyncBufferReader::read`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct ReadFuture<'a, T: Sized> {
+    reader: &'a mut BufferReader<T>,
+}
+
+impl<T: Sized> Future for ReadFuture<'_, T> {
+    type Output = Result<T, DisconnectedError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.reader.poll_read(cx)
+    }
+}
+
+impl<T: Sized> Drop for ReadFuture<'_, T> {
+    fn drop(&mut self) {
+        self.reader.shared_state.rd_waiter.cancel_wait();
+    }
+}
+
+impl<T: Sized> Stream for BufferReader<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().poll_read(cx).map(Result::ok)
+    }
+}
+
+impl<T: Sized> Stream for AsyncBufferReader<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.get_mut().reader).poll_next(cx)
+    }
+}
+
+/// Elements are written straight into the ring, so flushing completes immediately and closing
+/// closes the ring.
+///
+/// `start_send` panics if it is called without a preceding successful `poll_ready`.
+impl<T: Sized> Sink<T> for BufferWriter<T> {
+    type Error = DisconnectedError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        self.get_mut().poll_space(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), DisconnectedError> {
+        match self.get_mut().try_write(item) {
+            Ok(()) => Ok(()),
+            Err(TryWriteError::Disconnected(_)) => Err(DisconnectedError),
+            Err(TryWriteError::Full(_)) => {
+                panic!("start_send called on a full ring buffer without poll_ready")
+            }
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        self.get_mut().close();
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Like the `Sink` of [`BufferWriter`], but the values of dropped write futures are written
+/// first. Flushing writes them, closing does the same and then closes the ring.
+impl<T: Sized> Sink<T> for AsyncBufferWriter<T> {
+    type Error = DisconnectedError;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        let this = self.get_mut();
+
+        ready!(this.poll_pending(cx))?;
+
+        Pin::new(&mut this.writer).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), DisconnectedError> {
+        Pin::new(&mut self.get_mut().writer).start_send(item)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), DisconnectedError>> {
+        let this = self.get_mut();
+
+        let result = ready!(this.poll_pending(cx));
+
+        this.writer.close();
+
+        Poll::Ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll};
+
+    use futures::executor::block_on;
+    use futures::task::noop_waker;
+    use futures::{SinkExt, StreamExt};
+
+    use crate::{create_ring_buffer, DisconnectedError, TryReadError, WriteError};
+
+    #[test]
+    fn async_write_read_test() {
+        let (buffer_writer, buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let mut buffer_writer = buffer_writer.into_async();
+        let mut buffer_reader = buffer_reader.into_async();
+
+        let writer_thread = std::thread::spawn(move || {
+            block_on(async {
+                for idx in 0..64 {
+                    buffer_writer.write(idx).await.unwrap();
+                }
+            })
+        });
+
+        let received = block_on(async {
+            let mut received = Vec::new();
+
+            for _ in 0..64 {
+                received.push(buffer_reader.read().await.unwrap());
+            }
+
+            received
+        });
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(received, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn stream_sink_test() {
+        let (buffer_writer, buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let mut buffer_writer = buffer_writer.into_async();
+
+        let writer_thread = std::thread::spawn(move || {
+            block_on(async {
+                let mut values = futures::stream::iter((0..64).map(Ok));
+
+                buffer_writer.send_all(&mut values).await.unwrap();
+                buffer_writer.close().await.unwrap();
+            })
+        });
+
+        let received = block_on(buffer_reader.into_async().collect::<Vec<_>>());
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(received, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn plain_stream_sink_test() {
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let writer_thread = std::thread::spawn(move || {
+            block_on(async {
+                let mut values = futures::stream::iter((0..64).map(Ok));
+
+                buffer_writer.send_all(&mut values).await.unwrap();
+                SinkExt::close(&mut buffer_writer).await.unwrap();
+            })
+        });
+
+        let received = block_on(StreamExt::collect::<Vec<_>>(buffer_reader));
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(received, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn cancelled_write_test() {
+        let (buffer_writer, buffer_reader) = create_ring_buffer::<String>(1);
+
+        let mut buffer_writer = buffer_writer.into_async();
+        let mut buffer_reader = buffer_reader.into_async();
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(buffer_writer
+            .get_mut()
+            .try_write(String::from("first"))
+            .is_ok());
+
+        {
+            let mut write_future = pin!(buffer_writer.write(String::from("second")));
+
+            assert!(write_future.as_mut().poll(&mut cx).is_pending());
+        }
+
+        let write_future = buffer_writer.write(String::from("third"));
+
+        assert_eq!(write_future.into_inner().as_deref(), Some("third"));
+
+        assert_eq!(buffer_reader.get_mut().try_read().as_deref(), Ok("first"));
+        assert_eq!(buffer_reader.get_mut().try_read(), Err(TryReadError::Empty));
+
+        {
+            let mut read_future = pin!(buffer_reader.read());
+
+            assert_eq!(read_future.as_mut().poll(&mut cx), Poll::Pending);
+        }
+
+        drop(buffer_writer);
+
+        assert_eq!(block_on(buffer_reader.read()), Err(DisconnectedError));
+    }
+
+    #[test]
+    fn dropped_write_test() {
+        let (buffer_writer, buffer_reader) = create_ring_buffer::<String>(1);
+
+        let mut buffer_writer = buffer_writer.into_async();
+        let mut buffer_reader = buffer_reader.into_async();
+
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(block_on(buffer_writer.write(String::from("first"))).is_ok());
+
+        {
+            let mut write_future = pin!(buffer_writer.write(String::from("second")));
+
+            assert!(write_future.as_mut().poll(&mut cx).is_pending());
+        }
+
+        assert_eq!(buffer_writer.pending(), 1);
+        assert_eq!(block_on(buffer_reader.read()).as_deref(), Ok("first"));
+
+        // Written ahead of the next value once there is space.
+        {
+            let mut write_future = pin!(buffer_writer.write(String::from("third")));
+
+            assert!(write_future.as_mut().poll(&mut cx).is_pending());
+            assert_eq!(block_on(buffer_reader.read()).as_deref(), Ok("second"));
+            assert_eq!(write_future.poll(&mut cx), Poll::Ready(Ok(())));
+        }
+
+        assert_eq!(buffer_writer.pending(), 0);
+        assert_eq!(block_on(buffer_reader.read()).as_deref(), Ok("third"));
+        assert_eq!(buffer_reader.get_mut().try_read(), Err(TryReadError::Empty));
+
+        // Flushing writes the value of a dropped future as well.
+        {
+            let mut write_future = pin!(buffer_writer.write(String::from("fourth")));
+
+            assert!(write_future.as_mut().poll(&mut cx).is_ready());
+        }
+
+        drop(buffer_writer.write(String::from("fifth")));
+
+        assert_eq!(block_on(buffer_reader.read()).as_deref(), Ok("fourth"));
+        assert!(block_on(buffer_writer.flush()).is_ok());
+        assert_eq!(block_on(buffer_reader.read()).as_deref(), Ok("fifth"));
+        assert_eq!(buffer_reader.get_mut().try_read(), Err(TryReadError::Empty));
+    }
+
+    #[test]
+    fn async_disconnect_test() {
+        let (buffer_writer, buffer_reader) = create_ring_buffer::<u32>(2);
+
+        let mut buffer_writer = buffer_writer.into_async();
+
+        drop(buffer_reader);
+
+        assert_eq!(block_on(buffer_writer.write(1)), Err(WriteError(1)));
+        assert_eq!(block_on(buffer_writer.send(2)), Err(DisconnectedError));
+    }
+}
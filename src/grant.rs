@@ -0,0 +1,236 @@
+//! Zero-copy access to the slots of the ring.
+//!
+//! A [`WriteGrant`] hands out the free slots so elements can be constructed in place and a
+//! [`ReadGrant`] exposes the readable elements so they can be processed without moving them out.
+//! Either side only publishes its index once the grant is committed or released.
+
+use core::mem::MaybeUninit;
+use core::ops::Deref;
+
+use crate::bulk::BatchCursor;
+use crate::{BufferReader, BufferWriter, SharedBufferState};
+
+impl<T: Sized> BufferWriter<T> {
+    /// Reserves up to `count` free slots for in-place writes. The grant may contain fewer slots
+    /// if the ring doesn't have enough space and none if it is closed.
+    pub fn reserve(&mut self, count: usize) -> WriteGrant<'_, T> {
+        let (cur_write_idx, free_slots) = if self.shared_state.is_closed() {
+            (0, 0)
+        } else {
+            self.writable_slots(count)
+        };
+
+        WriteGrant {
+            writer: self,
+            start: cur_write_idx,
+            len: count.min(free_slots),
+        }
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Grants access to all elements that are readable right now without moving them out.
+    pub fn readable(&mut self) -> ReadGrant<'_, T> {
+        let (cur_read_idx, available) = self.readable_slots(usize::MAX);
+
+        ReadGrant {
+            len: available,
+            start: cur_read_idx,
+            reader: self,
+        }
+    }
+}
+
+/// Free slots reserved by [`BufferWriter::reserve`].
+///
+/// Dropping the grant without committing discards it, nothing is published.
+pub struct WriteGrant<'a, T: Sized> {
+    writer: &'a mut BufferWriter<T>,
+    start: u64,
+    len: usize,
+}
+
+impl<T: Sized> WriteGrant<'_, T> {
+    /// Number of reserved slots.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the reserved slots in order. The second slice is only non-empty if the
+    /// reservation wraps around the end of the ring.
+    pub fn as_mut_slices(&mut self) -> (&mut [MaybeUninit<T>], &mut [MaybeUninit<T>]) {
+        let (first, first_len, second, second_len) =
+            self.writer.shared_state.slot_ranges(self.start, self.len);
+
+        unsafe {
+            (
+                core::slice::from_raw_parts_mut(first as *mut MaybeUninit<T>, first_len),
+                core::slice::from_raw_parts_mut(second as *mut MaybeUninit<T>, second_len),
+            )
+        }
+    }
+
+    /// Publishes the first `count` reserved slots to the reader.
+    ///
+    /// # Safety
+    ///
+    /// The first `count` slots (in the order returned by [`WriteGrant::as_mut_slices`]) must
+    /// have been initialized.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is larger than the grant.
+    pub unsafe fn commit(self, count: usize) {
+        assert!(count <= self.len, "committing more slots than reserved");
+
+        if count > 0 {
+            let state = self.writer.shared_state.deref();
+
+            state.publish_write(self.start + count as u64);
+        }
+    }
+}
+
+/// Readable elements granted by [`BufferReader::readable`].
+///
+/// Dropping the grant without releasing leaves all elements in the ring.
+pub struct ReadGrant<'a, T: Sized> {
+    reader: &'a mut BufferReader<T>,
+    start: u64,
+    len: usize,
+}
+
+impl<T: Sized> ReadGrant<'_, T> {
+    /// Number of granted elements.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the granted elements in order. The second slice is only non-empty if they wrap
+    /// around the end of the ring.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        let (first, first_len, second, second_len) =
+            self.reader.shared_state.slot_ranges(self.start, self.len);
+
+        unsafe {
+            (
+                core::slice::from_raw_parts(first, first_len),
+                core::slice::from_raw_parts(second, second_len),
+            )
+        }
+    }
+
+    /// Drops the first `count` elements and hands their slots back to the writer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is larger than the grant.
+    pub fn release(self, count: usize) {
+        assert!(count <= self.len, "releasing more elements than granted");
+
+        let state = self.reader.shared_state.deref();
+
+        let mut cursor = BatchCursor::new(state, self.start, SharedBufferState::publish_read);
+
+        for _ in 0..count {
+            unsafe {
+                core::ptr::drop_in_place(cursor.next_slot());
+            }
+        }
+    }
+}
+
+impl<T: Sized> Drop for ReadGrant<'_, T> {
+    fn drop(&mut self) {
+        self.reader.shared_state.release_rd_index(self.start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::MaybeUninit;
+
+    use crate::create_ring_buffer;
+
+    #[test]
+    fn write_grant_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u64>(8);
+
+        assert_eq!(buffer_writer.write_slice(&[0, 1, 2, 3, 4, 5]), Ok(6));
+        assert_eq!(buffer_reader.read_to_vec(6), vec![0, 1, 2, 3, 4, 5]);
+
+        let mut grant = buffer_writer.reserve(10);
+
+        assert_eq!(grant.len(), 8);
+
+        let (first, second) = grant.as_mut_slices();
+
+        assert_eq!((first.len(), second.len()), (2, 6));
+
+        for (idx, slot) in first.iter_mut().chain(second.iter_mut()).enumerate() {
+            slot.write(idx as u64 * 10);
+        }
+
+        unsafe { grant.commit(4) };
+
+        assert_eq!(buffer_reader.read_to_vec(8), vec![0, 10, 20, 30]);
+
+        // Uncommitted grants publish nothing.
+        {
+            let mut grant = buffer_writer.reserve(1);
+
+            grant.as_mut_slices().0[0] = MaybeUninit::new(7);
+        }
+
+        assert_eq!(buffer_reader.size(), 0);
+
+        drop(buffer_reader);
+
+        assert!(buffer_writer.reserve(1).is_empty());
+    }
+
+    #[test]
+    fn read_grant_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<String>(4);
+
+        assert_eq!(
+            buffer_writer.write_from_iter(["a", "b"].map(String::from)),
+            Ok(2)
+        );
+        assert_eq!(buffer_reader.read_to_vec(2).len(), 2);
+        assert_eq!(
+            buffer_writer.write_from_iter(["c", "d", "e"].map(String::from)),
+            Ok(3)
+        );
+
+        {
+            let grant = buffer_reader.readable();
+
+            assert_eq!(grant.len(), 3);
+            assert_eq!(
+                grant.as_slices(),
+                (
+                    &["c", "d"].map(String::from)[..],
+                    &["e"].map(String::from)[..]
+                )
+            );
+        }
+
+        let grant = buffer_reader.readable();
+
+        assert_eq!(grant.len(), 3);
+
+        grant.release(2);
+
+        assert_eq!(buffer_reader.try_read().as_deref(), Ok("e"));
+        assert!(buffer_reader.readable().is_empty());
+    }
+}
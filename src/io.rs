@@ -0,0 +1,220 @@
+//! Byte stream access to `u8` rings through the `std::io` traits.
+//!
+//! Neither side blocks: a write into a full ring and a read from an empty one fail with
+//! [`io::ErrorKind::WouldBlock`]. Once the reader is gone writes fail with
+//! [`io::ErrorKind::BrokenPipe`], once the writer is gone and everything has been read the
+//! reader reports end of file.
+//!
+//! The blocking [`BufferWriter::write`] and [`BufferReader::read`] take precedence over the
+//! trait methods of the same name, call those as `Write::write(&mut buffer_writer, buf)` and
+//! `Read::read(&mut buffer_reader, buf)` or use the other methods of the traits.
+
+use std::io;
+
+use crate::atomic::Ordering;
+use crate::{BufferReader, BufferWriter};
+
+/// Copies as many bytes as fit into the ring. Returns the number of bytes written, which may be
+/// less than `buf.len()`, or [`io::ErrorKind::WouldBlock`] if the ring is full.
+impl io::Write for BufferWriter<u8> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.write_slice(buf) {
+            Err(_) => Err(io::ErrorKind::BrokenPipe.into()),
+            Ok(0) if !buf.is_empty() => Err(io::ErrorKind::WouldBlock.into()),
+            Ok(count) => Ok(count),
+        }
+    }
+
+    /// Every written byte is visible to the reader right away, there is nothing to flush.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies the readable bytes into `buf`, fails with [`io::ErrorKind::WouldBlock`] if there are
+/// none yet.
+impl io::Read for BufferReader<u8> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let available = io::BufRead::fill_buf(self)?;
+
+        let count = available.len().min(buf.len());
+
+        buf[..count].copy_from_slice(&available[..count]);
+
+        io::BufRead::consume(self, count);
+
+        Ok(count)
+    }
+}
+
+/// Exposes the readable bytes in place. [`fill_buf`](io::BufRead::fill_buf) returns the part
+/// up to the end of the storage, the bytes after the wrap around follow once those have been
+/// consumed.
+impl io::BufRead for BufferReader<u8> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let (mut cur_read_idx, mut available) = self.readable_slots(usize::MAX);
+
+        if available == 0 {
+            if !self.is_closed() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            // The writer may have published more bytes right before closing.
+            (cur_read_idx, available) = self.readable_slots(usize::MAX);
+        }
+
+        let (first, first_len, _, _) = self.shared_state.slot_ranges(cur_read_idx, available);
+
+        // The read index stays claimed until `consume`, so an evicting writer can't take the
+        // bytes while the caller looks at them.
+        Ok(unsafe { core::slice::from_raw_parts(first, first_len) })
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let state = &*self.shared_state;
+
+        let cur_read_idx = state.load_rd_index(Ordering::Relaxed);
+
+        if amt == 0 {
+            state.release_rd_index(cur_read_idx);
+
+            return;
+        }
+
+        let readable = state.used_slots(cur_read_idx, self.cached_wr_index);
+
+        // `fill_buf` only handed out the bytes up to the end of the storage.
+        let (_, contiguous, _, _) = state.slot_ranges(cur_read_idx, readable);
+
+        assert!(
+            amt <= contiguous,
+            "consuming more bytes than fill_buf returned"
+        );
+
+        state.publish_read(cur_read_idx + amt as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, ErrorKind, Read, Write};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use crate::create_ring_buffer;
+
+    #[test]
+    fn write_read_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u8>(8);
+
+        assert_eq!(Write::write(&mut buffer_writer, b"hello").unwrap(), 5);
+        assert_eq!(Write::write(&mut buffer_writer, b" world").unwrap(), 3);
+        assert_eq!(
+            Write::write(&mut buffer_writer, b"rld").unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        let mut buf = [0u8; 6];
+
+        assert_eq!(Read::read(&mut buffer_reader, &mut buf).unwrap(), 6);
+        assert_eq!(&buf, b"hello ");
+
+        // Wraps around the end of the storage.
+        buffer_writer.write_all(b"world!").unwrap();
+
+        let mut received = Vec::new();
+
+        assert_eq!(
+            buffer_reader.read_to_end(&mut received).unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+        assert_eq!(received, b"woworld!");
+
+        drop(buffer_writer);
+
+        assert_eq!(Read::read(&mut buffer_reader, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn fill_buf_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u8>(4);
+
+        assert_eq!(
+            buffer_reader.fill_buf().unwrap_err().kind(),
+            ErrorKind::WouldBlock
+        );
+
+        buffer_writer.write_all(b"abc").unwrap();
+
+        assert_eq!(buffer_reader.fill_buf().unwrap(), b"abc");
+
+        buffer_reader.consume(2);
+        buffer_writer.write_all(b"de").unwrap();
+
+        assert_eq!(buffer_reader.fill_buf().unwrap(), b"cd");
+
+        buffer_reader.consume(0);
+
+        assert_eq!(buffer_reader.fill_buf().unwrap(), b"cd");
+
+        // The "e" after the wrap around hasn't been handed out yet.
+        assert!(catch_unwind(AssertUnwindSafe(|| buffer_reader.consume(3))).is_err());
+
+        buffer_reader.consume(2);
+
+        assert_eq!(buffer_reader.fill_buf().unwrap(), b"e");
+
+        buffer_reader.consume(1);
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            Write::write(&mut buffer_writer, b"f").unwrap_err().kind(),
+            ErrorKind::BrokenPipe
+        );
+    }
+
+    #[test]
+    fn byte_stream_threaded_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u8>(64);
+
+        let num_bytes = if cfg!(miri) { 1_000 } else { 100_000 };
+
+        let data = (0..num_bytes).map(|idx| idx as u8).collect::<Vec<_>>();
+
+        let writer_thread = {
+            let data = data.clone();
+
+            std::thread::spawn(move || {
+                let mut remaining = &data[..];
+
+                while !remaining.is_empty() {
+                    match Write::write(&mut buffer_writer, remaining) {
+                        Ok(count) => remaining = &remaining[count..],
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            })
+        };
+
+        let mut received = Vec::new();
+        let mut buf = [0u8; 100];
+
+        loop {
+            match Read::read(&mut buffer_reader, &mut buf) {
+                Ok(0) => break,
+                Ok(count) => received.extend_from_slice(&buf[..count]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => std::thread::yield_now(),
+                Err(e) => panic!("{}", e),
+            }
+        }
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(received, data);
+    }
+}
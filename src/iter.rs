@@ -0,0 +1,213 @@
+//! Standard iterator integration for the ring buffer handles.
+//!
+//! [`BufferReader`] is itself a non-blocking [`Iterator`] that ends as soon as the ring is empty.
+//! Since every iterator already is its own `IntoIterator`, the blocking variant that only ends
+//! once the ring is disconnected is available through [`BufferReader::into_blocking_iter`] and
+//! [`BufferReader::blocking_iter`].
+
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use crate::bulk::BatchCursor;
+use crate::{BufferReader, SharedBufferState};
+
+#[cfg(feature = "std")]
+use crate::BufferWriter;
+
+/// Yields elements until the ring is empty, see [`BufferReader::try_read`].
+impl<T: Sized> Iterator for BufferReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.try_read().ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.size(), None)
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Removes the elements that are readable right now and yields them. The read index is
+    /// published once the iterator is dropped, elements not consumed by then are dropped.
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        let (cur_read_idx, available) = self.readable_slots(usize::MAX);
+
+        let state = self.shared_state.deref();
+
+        Drain {
+            remaining: available,
+            cursor: BatchCursor::new(state, cur_read_idx, SharedBufferState::publish_read),
+            _reader: PhantomData,
+        }
+    }
+
+    /// Returns an iterator that blocks until the next element is available and ends once the
+    /// ring is empty and disconnected.
+    #[cfg(feature = "std")]
+    pub fn blocking_iter(&mut self) -> BlockingIter<'_, T> {
+        BlockingIter { reader: self }
+    }
+
+    /// Owning version of [`BufferReader::blocking_iter`].
+    #[cfg(feature = "std")]
+    pub fn into_blocking_iter(self) -> IntoBlockingIter<T> {
+        IntoBlockingIter { reader: self }
+    }
+}
+
+/// Iterator returned by [`BufferReader::drain`].
+pub struct Drain<'a, T: Sized> {
+    cursor: BatchCursor<'a, T>,
+    remaining: usize,
+    _reader: PhantomData<&'a mut BufferReader<T>>,
+}
+
+impl<T: Sized> Iterator for Drain<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        Some(unsafe { core::ptr::read(self.cursor.next_slot()) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Sized> ExactSizeIterator for Drain<'_, T> {}
+
+impl<T: Sized> FusedIterator for Drain<'_, T> {}
+
+// Only the elements are handed out, the shared state is borrowed from the reader.
+unsafe impl<T: Send> Send for Drain<'_, T> {}
+unsafe impl<T: Sync> Sync for Drain<'_, T> {}
+
+impl<T: Sized> Drop for Drain<'_, T> {
+    fn drop(&mut self) {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+
+            unsafe {
+                core::ptr::drop_in_place(self.cursor.next_slot());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+/// Iterator returned by [`BufferReader::blocking_iter`].
+pub struct BlockingIter<'a, T: Sized> {
+    reader: &'a mut BufferReader<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: Sized> Iterator for BlockingIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.reader.read().ok()
+    }
+}
+
+#[cfg(feature = "std")]
+/// Iterator returned by [`BufferReader::into_blocking_iter`].
+pub struct IntoBlockingIter<T: Sized> {
+    reader: BufferReader<T>,
+}
+
+#[cfg(feature = "std")]
+impl<T: Sized> Iterator for IntoBlockingIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.reader.read().ok()
+    }
+}
+
+#[cfg(feature = "std")]
+/// Writes all elements, blocking while the ring is full. Stops once the ring is disconnected,
+/// the element that could not be written and the rest of the iterator are dropped.
+impl<T: Sized> Extend<T> for BufferWriter<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+
+        loop {
+            if self.write_from_iter(iter.by_ref()).is_err() {
+                break;
+            }
+
+            match iter.next() {
+                Some(v) => {
+                    if self.write(v).is_err() {
+                        break;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use crate::create_ring_buffer;
+
+    #[test]
+    fn iterator_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(8);
+
+        buffer_writer.extend(0..5);
+
+        assert_eq!(
+            buffer_reader.by_ref().take(2).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+        assert_eq!(buffer_reader.by_ref().map(|v| v * 2).sum::<u32>(), 18);
+        assert_eq!(buffer_reader.next(), None);
+    }
+
+    #[test]
+    fn drain_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<String>(4);
+
+        buffer_writer.extend(["a", "b", "c"].map(String::from));
+
+        assert_eq!(buffer_reader.drain().len(), 3);
+        assert_eq!(buffer_reader.size(), 0);
+
+        buffer_writer.extend(["d", "e"].map(String::from));
+
+        let mut drain = buffer_reader.drain();
+
+        assert_eq!(drain.next().as_deref(), Some("d"));
+        assert!(buffer_writer.try_write(String::from("f")).is_ok());
+        assert_eq!(drain.next().as_deref(), Some("e"));
+        assert_eq!(drain.next(), None);
+
+        drop(drain);
+
+        assert_eq!(buffer_reader.drain().collect::<Vec<_>>(), vec!["f"]);
+    }
+
+    #[test]
+    fn blocking_iter_test() {
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let reader_thread =
+            std::thread::spawn(move || buffer_reader.into_blocking_iter().collect::<Vec<_>>());
+
+        buffer_writer.extend(0..100);
+
+        drop(buffer_writer);
+
+        assert_eq!(reader_thread.join().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+}
@@ -1,45 +1,404 @@
-use std::marker;
-use std::marker::PhantomData;
-use std::mem::MaybeUninit;
-use std::ops::Deref;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-
+//! Lock-free single producer, single consumer ring buffers.
+//!
+//! The crate is `no_std` compatible, its features are:
+//!
+//! * `std` (default): blocking reads and writes, [`ReaderSet`] for blocking on several rings,
+//!   `std::io` traits for `u8` rings. Implies `alloc`.
+//! * `alloc`: the heap allocated rings created by [`create_ring_buffer`] and
+//!   [`create_compact_ring_buffer`]. Without it only [`StaticRingBuffer`] is available.
+//! * `async`: `Sink` and `Stream` for the ring handles, and `AsyncBufferWriter` and
+//!   `AsyncBufferReader` in `future`, whose writes and reads can be `.await`ed. Implies `std`.
+//! * `portable-atomic`: takes the atomics from the `portable-atomic` crate, for targets without
+//!   native 64-bit atomics.
+//! * `critical-section`: lets `portable-atomic` fall back to a `critical-section`
+//!   implementation provided by the application.
+//! * `shm`: rings in shared memory for passing elements between processes, Linux only.
+//!   Implies `std`.
+//! * `persist`: rings kept in a memory mapped file that survive a restart, Linux only.
+//!   Implies `std`.
+//! * `eventfd`: readiness file descriptors of [`BufferReader`] and [`BufferWriter`] for `epoll`
+//!   based event loops, Linux only. Implies `std`.
+//! * `mio`: registers [`BufferReader`] and [`BufferWriter`] with `mio`. Implies `eventfd`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "alloc")]
+use alloc::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+#[cfg(feature = "alloc")]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use core::marker;
+#[cfg(feature = "alloc")]
+use core::marker::PhantomData;
+#[cfg(feature = "alloc")]
+use core::ops::Deref;
+#[cfg(feature = "alloc")]
+use core::ptr::NonNull;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+mod atomic;
+mod cache_padded;
+mod static_ring;
+
+#[cfg(feature = "alloc")]
+mod bip;
+#[cfg(feature = "alloc")]
+mod broadcast;
+#[cfg(feature = "alloc")]
+mod bulk;
+#[cfg(feature = "alloc")]
+mod grant;
+#[cfg(feature = "std")]
+mod io;
+#[cfg(feature = "alloc")]
+mod iter;
+#[cfg(feature = "alloc")]
+mod mpmc;
+#[cfg(feature = "alloc")]
+mod mpsc;
+#[cfg(feature = "alloc")]
+mod peek;
+#[cfg(feature = "alloc")]
+mod policy;
+#[cfg(all(feature = "eventfd", target_os = "linux"))]
+mod readiness;
+#[cfg(feature = "alloc")]
+mod record;
+#[cfg(all(any(feature = "shm", feature = "persist"), target_os = "linux"))]
+mod mmap;
+#[cfg(all(feature = "persist", target_os = "linux"))]
+mod persist;
+#[cfg(feature = "std")]
+mod select;
+#[cfg(feature = "alloc")]
+mod stamped;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+mod shm;
+#[cfg(feature = "std")]
+mod wait;
+
+pub mod error;
+
+#[cfg(feature = "async")]
+pub mod future;
+
+#[cfg(feature = "alloc")]
+use atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+#[cfg(feature = "alloc")]
+use cache_padded::CachePadded;
+#[cfg(feature = "std")]
+use wait::WaitSlot;
+
+#[cfg(feature = "alloc")]
+pub use bip::{create_bip_buffer, BipReadGrant, BipReader, BipWriteGrant, BipWriter};
+#[cfg(feature = "alloc")]
+pub use broadcast::{
+    create_broadcast_ring_buffer, create_lagging_broadcast_ring_buffer, BroadcastReader,
+    BroadcastWriter,
+};
+#[cfg(feature = "alloc")]
+pub use grant::{ReadGrant, WriteGrant};
+#[cfg(feature = "alloc")]
+pub use iter::Drain;
+#[cfg(feature = "std")]
+pub use iter::{BlockingIter, IntoBlockingIter};
+#[cfg(all(any(feature = "shm", feature = "persist"), target_os = "linux"))]
+pub use mmap::Pod;
+#[cfg(feature = "alloc")]
+pub use mpmc::{create_mpmc_queue, MpmcQueue, MpmcReader, MpmcWriter};
+#[cfg(feature = "alloc")]
+pub use mpsc::{create_mpsc_ring_buffer, MpscReader, MpscWriter};
+#[cfg(feature = "alloc")]
+pub use peek::{Iter, PeekMut, PeekRef};
+#[cfg(all(feature = "persist", target_os = "linux"))]
+pub use persist::{open_persistent_ring, PersistentReader, PersistentWriter, SyncPolicy};
+#[cfg(feature = "alloc")]
+pub use policy::FullPolicy;
+#[cfg(feature = "alloc")]
+pub use record::{
+    create_record_ring_buffer, RecordGrant, RecordGuard, RecordReader, RecordWriter,
+};
+#[cfg(feature = "std")]
+pub use select::ReaderSet;
+#[cfg(all(feature = "shm", target_os = "linux"))]
+pub use shm::{
+    create_shm, create_shm_memfd, open_shm, receive_shm, ShmReader, ShmWriter,
+};
+pub use static_ring::{StaticBufferReader, StaticBufferWriter, StaticRingBuffer};
+
+pub use error::{
+    DisconnectedError, ReadTimeoutError, TryBroadcastReadError, TryReadError, TryWriteError,
+    TryReserveError, WriteError, WriteTimeoutError,
+};
+
+/// State shared between the writer and the reader.
+///
+/// `wr_index` and `rd_index` are free running counters of the elements written and read so far,
+/// the number of elements in the ring is always `wr_index - rd_index`. They are mapped onto the
+/// `slot_count` slots of `storage` by masking, or by a modulo if the ring was created with
+/// [`create_compact_ring_buffer`].
+///
+/// Both indices live on their own cache line, so the writer storing `wr_index` doesn't
+/// invalidate the line the reader keeps storing `rd_index` to and vice versa.
+///
+/// With [`FullPolicy::OverwriteOldest`] the writer may advance `rd_index` as well to evict the
+/// oldest element. The reader then sets [`RD_BUSY`] in `rd_index` while it accesses slots and
+/// the writer only evicts with a compare-exchange on an index without it.
+#[cfg(feature = "alloc")]
 #[allow(dead_code)]
-
 struct SharedBufferState<T: Sized> {
     ring_capacity: u64,
-    element_size: u64,
 
-    wr_index: AtomicU64,
-    rd_index: AtomicU64,
+    policy: FullPolicy,
+
+    slot_count: u64,
+    slot_mask: u64,
+    masked: bool,
+
+    wr_index: CachePadded<AtomicU64>,
+    rd_index: CachePadded<AtomicU64>,
+
+    closed: AtomicBool,
 
-    storage: bytes::BytesMut,
+    // Number of elements dropped by `DropNewest` or `OverwriteOldest`, only written by the writer.
+    evicted: AtomicU64,
+
+    #[cfg(feature = "std")]
+    rd_waiter: WaitSlot,
+    #[cfg(feature = "std")]
+    wr_waiter: WaitSlot,
+
+    // Allocated with the layout of `[T; slot_count]`, dangling if that is zero sized.
+    storage: NonNull<T>,
 
     _marker: marker::PhantomData<T>,
 }
 
+/// Writing half of a ring buffer.
+///
+/// The writer can be moved to another thread if `T` is [`Send`]:
+///
+/// ```compile_fail
+/// let (buffer_writer, _buffer_reader) = atomic_ring_buffer::create_ring_buffer::<std::rc::Rc<u32>>(1);
+///
+/// std::thread::spawn(move || drop(buffer_writer));
+/// ```
+#[cfg(feature = "alloc")]
 pub struct BufferWriter<T: Sized> {
     shared_state: Arc<SharedBufferState<T>>,
+
+    // Last value of `rd_index` seen by the writer. It only ever lags behind, so it is only
+    // reloaded once the ring looks full.
+    cached_rd_index: u64,
 }
 
+/// Reading half of a ring buffer.
+///
+/// The reader can be moved to another thread if `T` is [`Send`]:
+///
+/// ```compile_fail
+/// let (_buffer_writer, buffer_reader) = atomic_ring_buffer::create_ring_buffer::<std::rc::Rc<u32>>(1);
+///
+/// std::thread::spawn(move || drop(buffer_reader));
+/// ```
+///
+/// Sharing it additionally requires `T` to be [`Sync`], since [`BufferReader::peek`] hands out
+/// references to the elements:
+///
+/// ```compile_fail
+/// let (_buffer_writer, buffer_reader) = atomic_ring_buffer::create_ring_buffer::<std::cell::Cell<u32>>(1);
+///
+/// std::thread::scope(|s| {
+///     s.spawn(|| buffer_reader.peek().map(|v| v.set(1)));
+/// });
+/// ```
+#[cfg(feature = "alloc")]
 pub struct BufferReader<T: Sized> {
     shared_state: Arc<SharedBufferState<T>>,
+
+    // Last value of `wr_index` seen by the reader, only reloaded once the ring looks empty.
+    cached_wr_index: u64,
+
+    // Number of live borrows handed out by `peek`, `get` and `iter`, which share one claim of
+    // `rd_index`. See `peek::BORROWS_LOCKED`.
+    borrows: AtomicUsize,
 }
 
+/// Flag in `rd_index` marking that the reader accesses the readable slots.
+#[cfg(feature = "alloc")]
+const RD_BUSY: u64 = 1 << 63;
+
+#[cfg(feature = "alloc")]
 impl<T: Sized> SharedBufferState<T> {
     pub fn size(&self) -> usize {
-        let cur_read_idx = self.rd_index.load(Ordering::Acquire);
+        let cur_read_idx = self.load_rd_index(Ordering::Acquire);
         let cur_write_idx = self.wr_index.load(Ordering::Acquire);
 
-        ((cur_write_idx + self.ring_capacity - cur_read_idx) % self.ring_capacity) as usize
+        self.used_slots(cur_read_idx, cur_write_idx)
     }
 
     pub fn capacity(&self) -> usize {
         self.ring_capacity as usize
     }
+
+    fn used_slots(&self, cur_read_idx: u64, cur_write_idx: u64) -> usize {
+        cur_write_idx.wrapping_sub(cur_read_idx) as usize
+    }
+
+    fn free_slots(&self, cur_read_idx: u64, cur_write_idx: u64) -> usize {
+        self.capacity() - self.used_slots(cur_read_idx, cur_write_idx)
+    }
+
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    fn is_full(&self) -> bool {
+        self.size() == self.capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+
+        #[cfg(feature = "std")]
+        self.rd_waiter.notify();
+        #[cfg(feature = "std")]
+        self.wr_waiter.notify();
+    }
+
+    fn publish_write(&self, index: u64) {
+        self.wr_index.store(index, Ordering::Release);
+
+        #[cfg(feature = "std")]
+        self.rd_waiter.notify();
+    }
+
+    fn load_rd_index(&self, order: Ordering) -> u64 {
+        self.rd_index.load(order) & !RD_BUSY
+    }
+
+    /// Returns the read index before the reader accesses the readable slots. If the writer may
+    /// evict elements the index is marked busy first, which keeps it from doing so until the
+    /// reader publishes a new index or calls [`SharedBufferState::release_rd_index`].
+    fn claim_rd_index(&self) -> u64 {
+        if self.policy != FullPolicy::OverwriteOldest {
+            return self.rd_index.load(Ordering::Relaxed);
+        }
+
+        let mut cur_read_idx = self.rd_index.load(Ordering::Acquire);
+
+        loop {
+            if cur_read_idx & RD_BUSY != 0 {
+                return cur_read_idx & !RD_BUSY;
+            }
+
+            match self.rd_index.compare_exchange_weak(
+                cur_read_idx,
+                cur_read_idx | RD_BUSY,
+                Ordering::Acquire,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return cur_read_idx,
+                Err(v) => cur_read_idx = v,
+            }
+        }
+    }
+
+    /// Clears the busy flag set by [`SharedBufferState::claim_rd_index`] if `index` hasn't been
+    /// published in the meantime.
+    fn release_rd_index(&self, index: u64) {
+        if self.policy == FullPolicy::OverwriteOldest {
+            let _ = self.rd_index.compare_exchange(
+                index | RD_BUSY,
+                index,
+                Ordering::Release,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    fn publish_read(&self, index: u64) {
+        self.rd_index.store(index, Ordering::Release);
+
+        #[cfg(feature = "std")]
+        self.wr_waiter.notify();
+    }
+
+    fn slot_of(&self, index: u64) -> u64 {
+        if self.masked {
+            index & self.slot_mask
+        } else {
+            index % self.slot_count
+        }
+    }
+
+    fn slot_ptr(&self, index: u64) -> *mut T {
+        unsafe { self.storage.as_ptr().add(self.slot_of(index) as usize) }
+    }
+
+    /// Returns the up to two contiguous slot ranges holding the `len` elements starting at
+    /// `index`. The second range is only non-empty if they wrap around the end of the storage.
+    fn slot_ranges(&self, index: u64, len: usize) -> (*mut T, usize, *mut T, usize) {
+        let first_len = len.min((self.slot_count - self.slot_of(index)) as usize);
+
+        (
+            self.slot_ptr(index),
+            first_len,
+            self.slot_ptr(index + first_len as u64),
+            len - first_len,
+        )
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T: Sized> Drop for SharedBufferState<T> {
+    fn drop(&mut self) {
+        // Frees the storage even if dropping one of the remaining elements panics.
+        struct Dealloc<T>(NonNull<T>, usize);
+
+        impl<T> Drop for Dealloc<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    dealloc_slots(self.0, self.1);
+                }
+            }
+        }
+
+        let _dealloc = Dealloc(self.storage, self.slot_count as usize);
+
+        let cur_read_idx = *self.rd_index.0.get_mut() & !RD_BUSY;
+        let cur_write_idx = *self.wr_index.0.get_mut();
+
+        let (first, first_len, second, second_len) =
+            self.slot_ranges(cur_read_idx, self.used_slots(cur_read_idx, cur_write_idx));
+
+        // Dropping a slice keeps dropping the other elements if one of them panics.
+        unsafe {
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(first, first_len));
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(second, second_len));
+        }
+    }
 }
 
+// Elements are only ever accessed by one side at a time, so handing them from one thread to
+// the other only requires them to be `Send`. Shared access to the writer never touches
+// elements, shared access to the reader does through `peek`/`iter`.
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for BufferWriter<T> {}
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Sync for BufferWriter<T> {}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send> Send for BufferReader<T> {}
+#[cfg(feature = "alloc")]
+unsafe impl<T: Send + Sync> Sync for BufferReader<T> {}
+
+#[cfg(feature = "alloc")]
 impl<T: Sized> BufferWriter<T> {
     pub fn size(&self) -> usize {
         let state = self.shared_state.deref();
@@ -53,38 +412,143 @@ impl<T: Sized> BufferWriter<T> {
         state.capacity()
     }
 
-    pub fn try_write(&mut self, value: T) -> Result<(), T> {
-        let mut v = MaybeUninit::new(value);
+    /// Returns true once either side closed the ring or the reader has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
 
-        let state = self.shared_state.deref();
+    /// Closes the ring. Elements already written can still be read, further writes fail.
+    pub fn close(&mut self) {
+        self.shared_state.close();
+    }
 
-        let cur_read_idx = state.rd_index.load(Ordering::Acquire);
-        let cur_write_idx = state.wr_index.load(Ordering::Acquire);
+    pub fn try_write(&mut self, value: T) -> Result<(), TryWriteError<T>> {
+        if self.shared_state.is_closed() {
+            return Err(TryWriteError::Disconnected(value));
+        }
+
+        let (cur_write_idx, free_slots) = self.writable_slots(1);
 
-        if ((cur_write_idx + state.ring_capacity - cur_read_idx) % state.ring_capacity)
-            == (state.ring_capacity - 1)
-        {
-            return Err(unsafe { v.assume_init() });
+        if free_slots == 0 {
+            return Err(TryWriteError::Full(value));
         }
 
-        unsafe {
-            let dst_ptr = state
-                .storage
-                .as_ptr()
-                .offset((cur_write_idx * state.element_size) as isize)
-                as *mut T;
+        let state = self.shared_state.deref();
 
-            std::mem::swap(&mut *v.as_mut_ptr(), &mut *dst_ptr);
+        // The slot is free, so it holds no value that would have to be dropped first.
+        unsafe {
+            core::ptr::write(state.slot_ptr(cur_write_idx), value);
         }
 
-        state
-            .wr_index
-            .store((cur_write_idx + 1) % state.ring_capacity, Ordering::Release);
+        state.publish_write(cur_write_idx + 1);
 
         Ok(())
     }
+
+    /// Returns the write index and the number of free slots. The reader's index is only reloaded
+    /// if the cached copy leaves fewer than `wanted` slots free.
+    fn writable_slots(&mut self, wanted: usize) -> (u64, usize) {
+        let state = self.shared_state.deref();
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+
+        let mut free_slots = state.free_slots(self.cached_rd_index, cur_write_idx);
+
+        if free_slots < wanted {
+            self.cached_rd_index = state.load_rd_index(Ordering::Acquire);
+
+            free_slots = state.free_slots(self.cached_rd_index, cur_write_idx);
+        }
+
+        #[cfg(all(feature = "eventfd", target_os = "linux"))]
+        if free_slots == 0 && state.wr_waiter.readiness.arm() {
+            self.cached_rd_index = state.load_rd_index(Ordering::Acquire);
+
+            free_slots = state.free_slots(self.cached_rd_index, cur_write_idx);
+
+            if free_slots != 0 {
+                state.wr_waiter.readiness.disarm();
+            }
+        }
+
+        (cur_write_idx, free_slots)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Sized> BufferWriter<T> {
+    /// Writes `value` into the buffer, blocking the current thread until there is space for it.
+    /// Fails and returns the value back if the ring gets disconnected.
+    pub fn write(&mut self, value: T) -> Result<(), WriteError<T>> {
+        self.write_until(value, None)
+            .map_err(|e| WriteError(e.into_inner()))
+    }
+
+    /// Like [`BufferWriter::write`] but gives up after `timeout` and returns the value back.
+    pub fn write_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), WriteTimeoutError<T>> {
+        self.write_until(value, Some(Instant::now() + timeout))
+    }
+
+    /// Like [`BufferWriter::write`] but gives up once `deadline` has passed and returns the value back.
+    pub fn write_deadline(
+        &mut self,
+        value: T,
+        deadline: Instant,
+    ) -> Result<(), WriteTimeoutError<T>> {
+        self.write_until(value, Some(deadline))
+    }
+
+    fn write_until(
+        &mut self,
+        mut value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), WriteTimeoutError<T>> {
+        loop {
+            value = match self.try_write(value) {
+                Ok(()) => return Ok(()),
+                Err(TryWriteError::Full(v)) => v,
+                Err(TryWriteError::Disconnected(v)) => {
+                    return Err(WriteTimeoutError::Disconnected(v))
+                }
+            };
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(WriteTimeoutError::Timeout(value));
+            }
+
+            self.shared_state.wr_waiter.prepare_wait();
+
+            value = match self.try_write(value) {
+                Ok(()) => {
+                    self.shared_state.wr_waiter.cancel_wait();
+
+                    return Ok(());
+                }
+                Err(TryWriteError::Full(v)) => v,
+                Err(TryWriteError::Disconnected(v)) => {
+                    self.shared_state.wr_waiter.cancel_wait();
+
+                    return Err(WriteTimeoutError::Disconnected(v));
+                }
+            };
+
+            self.shared_state.wr_waiter.park(deadline);
+        }
+    }
 }
 
+#[cfg(feature = "alloc")]
+impl<T> Drop for BufferWriter<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+#[cfg(feature = "alloc")]
 impl<T: Sized> BufferReader<T> {
     pub fn size(&self) -> usize {
         let state = self.shared_state.deref();
@@ -98,93 +562,260 @@ impl<T: Sized> BufferReader<T> {
         state.capacity()
     }
 
-    pub fn try_read(&mut self) -> Option<T> {
+    /// Returns true once either side closed the ring or the writer has been dropped.
+    /// There may still be elements left to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Closes the ring. The writer can't write any more elements but the ones already written
+    /// can still be read.
+    pub fn close(&mut self) {
+        self.shared_state.close();
+    }
+
+    pub fn try_read(&mut self) -> Result<T, TryReadError> {
+        let (cur_read_idx, mut available) = self.readable_slots(1);
+
+        if available == 0 {
+            if !self.shared_state.is_closed() {
+                return Err(TryReadError::Empty);
+            }
+
+            // The writer may have published more elements right before closing.
+            (_, available) = self.readable_slots(1);
+
+            if available == 0 {
+                return Err(TryReadError::Disconnected);
+            }
+        }
+
         let state = self.shared_state.deref();
 
-        let cur_read_idx = state.rd_index.load(Ordering::Acquire);
-        let cur_write_idx = state.wr_index.load(Ordering::Acquire);
+        // The slot counts as free once the read index is published, the value is moved out.
+        let ret = unsafe { core::ptr::read(state.slot_ptr(cur_read_idx)) };
 
-        if cur_read_idx == cur_write_idx {
-            return Option::None;
+        state.publish_read(cur_read_idx + 1);
+
+        Ok(ret)
+    }
+
+    /// Returns the read index and the number of readable elements. The writer's index is only
+    /// reloaded if the cached copy shows fewer than `wanted` elements.
+    ///
+    /// The caller has to publish a new read index unless no elements or none were `wanted`.
+    fn readable_slots(&mut self, wanted: usize) -> (u64, usize) {
+        let state = self.shared_state.deref();
+
+        let cur_read_idx = state.claim_rd_index();
+
+        let mut available = state.used_slots(cur_read_idx, self.cached_wr_index);
+
+        // Evictions can move the read index past the cached write index.
+        if available < wanted || available > state.capacity() {
+            self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
+
+            available = state.used_slots(cur_read_idx, self.cached_wr_index);
         }
 
-        let ret = unsafe {
-            let src_ptr = state
-                .storage
-                .as_ptr()
-                .offset((cur_read_idx * state.element_size) as isize)
-                as *mut T;
+        #[cfg(all(feature = "eventfd", target_os = "linux"))]
+        if available == 0 && state.rd_waiter.readiness.arm() {
+            self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
 
-            let mut v = MaybeUninit::uninit();
+            available = state.used_slots(cur_read_idx, self.cached_wr_index);
 
-            std::mem::swap(&mut *src_ptr, &mut *v.as_mut_ptr());
+            if available != 0 {
+                state.rd_waiter.readiness.disarm();
+            }
+        }
 
-            Option::Some(v.assume_init())
-        };
+        if available == 0 || wanted == 0 {
+            state.release_rd_index(cur_read_idx);
+        }
+
+        (cur_read_idx, available)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Sized> BufferReader<T> {
+    /// Reads the next element, blocking the current thread until one is available.
+    /// Fails once the ring is empty and disconnected.
+    pub fn read(&mut self) -> Result<T, DisconnectedError> {
+        self.read_until(None).map_err(|_| DisconnectedError)
+    }
 
-        state
-            .rd_index
-            .store((cur_read_idx + 1) % state.ring_capacity, Ordering::Release);
+    /// Like [`BufferReader::read`] but gives up after `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<T, ReadTimeoutError> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
 
-        ret
+    /// Like [`BufferReader::read`] but gives up once `deadline` has passed.
+    pub fn read_deadline(&mut self, deadline: Instant) -> Result<T, ReadTimeoutError> {
+        self.read_until(Some(deadline))
+    }
+
+    fn read_until(&mut self, deadline: Option<Instant>) -> Result<T, ReadTimeoutError> {
+        loop {
+            match self.try_read() {
+                Ok(v) => return Ok(v),
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => return Err(ReadTimeoutError::Disconnected),
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(ReadTimeoutError::Timeout);
+            }
+
+            self.shared_state.rd_waiter.prepare_wait();
+
+            match self.try_read() {
+                Ok(v) => {
+                    self.shared_state.rd_waiter.cancel_wait();
+
+                    return Ok(v);
+                }
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => {
+                    self.shared_state.rd_waiter.cancel_wait();
+
+                    return Err(ReadTimeoutError::Disconnected);
+                }
+            }
+
+            self.shared_state.rd_waiter.park(deadline);
+        }
     }
 }
 
+#[cfg(feature = "alloc")]
 impl<T> Drop for BufferReader<T> {
     fn drop(&mut self) {
-        while self.try_read().is_some() {};
+        self.close();
+
+        self.drain().for_each(drop);
     }
 }
 
-fn size_align(type_size: usize, min_alignment: usize) -> usize {
-    let mut tmp = type_size / min_alignment;
+/// Creates a ring buffer that holds up to `buffer_capacity` elements.
+///
+/// The storage is rounded up to a power of two slots so that indices can be mapped onto slots
+/// with a mask. Use [`create_compact_ring_buffer`] to avoid the extra slots.
+#[cfg(feature = "alloc")]
+pub fn create_ring_buffer<T: Sized>(
+    buffer_capacity: usize,
+) -> (BufferWriter<T>, BufferReader<T>) {
+    create_ring_buffer_with_policy(buffer_capacity, FullPolicy::Reject)
+}
 
-    if (type_size % min_alignment) != 0 {
-        tmp += 1;
-    }
+/// Creates a ring buffer like [`create_ring_buffer`] whose writer handles a full ring according
+/// to `policy` in [`BufferWriter::push`].
+#[cfg(feature = "alloc")]
+pub fn create_ring_buffer_with_policy<T: Sized>(
+    buffer_capacity: usize,
+    policy: FullPolicy,
+) -> (BufferWriter<T>, BufferReader<T>) {
+    let actual_buffer_capacity = buffer_capacity.max(1);
 
-    tmp * min_alignment
+    new_ring_buffer(
+        actual_buffer_capacity,
+        actual_buffer_capacity.next_power_of_two(),
+        policy,
+    )
 }
 
-pub fn create_ring_buffer<T: Sized>(
+/// Creates a ring buffer whose storage holds exactly `buffer_capacity` slots, even if that is
+/// not a power of two. Mapping indices onto slots then needs a modulo instead of a mask.
+#[cfg(feature = "alloc")]
+pub fn create_compact_ring_buffer<T: Sized>(
     buffer_capacity: usize,
 ) -> (BufferWriter<T>, BufferReader<T>) {
+    let actual_buffer_capacity = buffer_capacity.max(1);
+
+    new_ring_buffer(actual_buffer_capacity, actual_buffer_capacity, FullPolicy::Reject)
+}
 
-    let actual_buffer_capacity = if buffer_capacity < 2 {
-        2
+#[cfg(feature = "alloc")]
+fn slots_layout<S>(slot_count: usize) -> Layout {
+    Layout::array::<S>(slot_count).expect("ring buffer capacity overflow")
+}
+
+/// Allocates uninitialized storage for `slot_count` slots of type `S`, shared by all rings with
+/// heap allocated slots.
+#[cfg(feature = "alloc")]
+fn alloc_slots<S>(slot_count: usize) -> NonNull<S> {
+    let layout = slots_layout::<S>(slot_count);
+
+    // Zero sized elements (or slots) don't need any memory, the ring then only counts them.
+    if layout.size() == 0 {
+        NonNull::dangling()
     } else {
-        buffer_capacity
-    };
+        let ptr = unsafe { alloc(layout) } as *mut S;
 
-    let element_size = size_align(std::mem::size_of::<T>(), std::mem::align_of::<*const T>());
+        NonNull::new(ptr).unwrap_or_else(|| handle_alloc_error(layout))
+    }
+}
+
+/// Frees storage returned by [`alloc_slots`] without dropping the slots.
+#[cfg(feature = "alloc")]
+unsafe fn dealloc_slots<S>(storage: NonNull<S>, slot_count: usize) {
+    let layout = slots_layout::<S>(slot_count);
+
+    if layout.size() != 0 {
+        dealloc(storage.as_ptr() as *mut u8, layout);
+    }
+}
 
-    let storage = bytes::BytesMut::with_capacity(element_size * actual_buffer_capacity);
+#[cfg(feature = "alloc")]
+fn new_ring_buffer<T: Sized>(
+    buffer_capacity: usize,
+    slot_count: usize,
+    policy: FullPolicy,
+) -> (BufferWriter<T>, BufferReader<T>) {
+    let storage = alloc_slots::<T>(slot_count);
 
     let shared_state = Arc::new(SharedBufferState {
-        ring_capacity: actual_buffer_capacity as u64,
-        element_size: element_size as u64,
-        wr_index: AtomicU64::new(0),
-        rd_index: AtomicU64::new(0),
+        ring_capacity: buffer_capacity as u64,
+        policy,
+        slot_count: slot_count as u64,
+        slot_mask: (slot_count as u64).wrapping_sub(1),
+        masked: slot_count.is_power_of_two(),
+        wr_index: CachePadded(AtomicU64::new(0)),
+        rd_index: CachePadded(AtomicU64::new(0)),
+        closed: AtomicBool::new(false),
+        evicted: AtomicU64::new(0),
+        #[cfg(feature = "std")]
+        rd_waiter: WaitSlot::new(),
+        #[cfg(feature = "std")]
+        wr_waiter: WaitSlot::new(),
         storage,
-        _marker: PhantomData::default(),
+        _marker: PhantomData,
     });
 
     (
         BufferWriter {
             shared_state: shared_state.clone(),
+            cached_rd_index: 0,
+        },
+        BufferReader {
+            shared_state,
+            cached_wr_index: 0,
+            borrows: AtomicUsize::new(0),
         },
-        BufferReader { shared_state },
     )
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod tests {
     use std::thread;
     use std::sync::Arc;
-    use std::sync::atomic::{AtomicBool};
-    use std::time::Duration;
+    use std::sync::atomic::{AtomicBool, AtomicUsize};
+    use std::time::{Duration, Instant};
 
-    use crate::create_ring_buffer;
+    use crate::{
+        create_compact_ring_buffer, create_ring_buffer, DisconnectedError, ReadTimeoutError, TryReadError, TryWriteError,
+        WriteError, WriteTimeoutError,
+    };
 
     #[test]
     fn basic_creation_test() {
@@ -209,8 +840,8 @@ mod tests {
         let read_item1 = buffer_reader.try_read();
         let read_item2 = buffer_reader.try_read();
 
-        assert!(read_item1.is_some());
-        assert!(read_item2.is_none());
+        assert!(read_item1.is_ok());
+        assert_eq!(read_item2.err(), Some(TryReadError::Empty));
 
         assert_eq!(read_item1.unwrap(), 1337u32);
     }
@@ -220,30 +851,35 @@ mod tests {
         let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(2);
 
         assert!(buffer_writer.try_write(1u32).is_ok());
-        assert!(buffer_writer.try_write(2u32).is_err());
+        assert!(buffer_writer.try_write(2u32).is_ok());
+        assert!(buffer_writer.try_write(3u32).is_err());
 
-        assert_eq!(buffer_writer.size(), 1);
-        assert_eq!(buffer_reader.size(), 1);
+        assert_eq!(buffer_writer.size(), 2);
+        assert_eq!(buffer_reader.size(), 2);
 
         let read_item1 = buffer_reader.try_read();
         let read_item2 = buffer_reader.try_read();
+        let read_item3 = buffer_reader.try_read();
 
-        assert!(read_item1.is_some());
-        assert!(read_item2.is_none());
+        assert!(read_item1.is_ok());
+        assert!(read_item2.is_ok());
+        assert_eq!(read_item3.err(), Some(TryReadError::Empty));
 
         assert_eq!(read_item1.unwrap(), 1u32);
+        assert_eq!(read_item2.unwrap(), 2u32);
     }
 
     #[derive(Clone)]
     struct SomeElementType {
         s: String,
+        #[allow(dead_code)]
         v: u32,
     }
 
     #[test]
     fn basic_element_test3() {
         let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<SomeElementType>(
-            2
+            1
         );
 
         let new_elem1 = SomeElementType {
@@ -265,8 +901,8 @@ mod tests {
         let read_item1 = buffer_reader.try_read();
         let read_item2 = buffer_reader.try_read();
 
-        assert!(read_item1.is_some());
-        assert!(read_item2.is_none());
+        assert!(read_item1.is_ok());
+        assert_eq!(read_item2.err(), Some(TryReadError::Empty));
 
         assert_eq!(read_item1.unwrap().s, "Element1");
     }
@@ -299,7 +935,7 @@ mod tests {
             while run_flag_reader.load(std::sync::atomic::Ordering::Acquire) {
                 let read_element = buffer_reader.try_read();
 
-                if let Some(element) = read_element {
+                if let Ok(element) = read_element {
                     if last_element == 0 {
                         last_element = element;
                     } else {
@@ -313,14 +949,274 @@ mod tests {
             println!("Last read element was {}", last_element);
         });
 
-        let run_duration = Duration::from_millis(2500);
+        let run_duration = Duration::from_millis(if cfg!(miri) { 50 } else { 2500 });
 
         thread::sleep(run_duration);
 
         run_flag.store(false, std::sync::atomic::Ordering::Release);
 
-        writer_thread.join();
-        reader_thread.join();
+        writer_thread.join().unwrap();
+        reader_thread.join().unwrap();
+
+    }
+
+    #[test]
+    fn blocking_read_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let reader_thread = std::thread::spawn(move || {
+            (0..16)
+                .map(|_| buffer_reader.read().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        for idx in 0..16 {
+            thread::sleep(Duration::from_millis(1));
+
+            buffer_writer.write(idx).unwrap();
+        }
+
+        assert_eq!(reader_thread.join().unwrap(), (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn blocking_write_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(2);
+
+        buffer_writer.write(1).unwrap();
+
+        let writer_thread = std::thread::spawn(move || {
+            buffer_writer.write(2).unwrap();
+            buffer_writer.write(3).unwrap();
+        });
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(buffer_reader.read(), Ok(1));
+        assert_eq!(buffer_reader.read(), Ok(2));
+        assert_eq!(buffer_reader.read(), Ok(3));
+
+        writer_thread.join().unwrap();
+    }
+
+    #[test]
+    fn timeout_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(1);
+
+        let tstart = Instant::now();
+
+        assert_eq!(
+            buffer_reader.read_timeout(Duration::from_millis(20)),
+            Err(ReadTimeoutError::Timeout)
+        );
+        assert!(tstart.elapsed() >= Duration::from_millis(20));
+
+        assert_eq!(
+            buffer_reader.read_deadline(Instant::now()),
+            Err(ReadTimeoutError::Timeout)
+        );
+
+        assert!(buffer_writer.write_timeout(1, Duration::from_millis(20)).is_ok());
+        assert_eq!(
+            buffer_writer.write_timeout(2, Duration::from_millis(20)),
+            Err(WriteTimeoutError::Timeout(2))
+        );
+
+        assert_eq!(buffer_reader.read_timeout(Duration::from_millis(20)), Ok(1));
+    }
+
+    #[test]
+    fn writer_disconnect_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(4);
+
+        assert!(buffer_writer.try_write(1).is_ok());
+        assert!(buffer_writer.try_write(2).is_ok());
+
+        drop(buffer_writer);
+
+        assert!(buffer_reader.is_closed());
+
+        assert_eq!(buffer_reader.try_read(), Ok(1));
+        assert_eq!(buffer_reader.read(), Ok(2));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Disconnected));
+        assert_eq!(buffer_reader.read(), Err(DisconnectedError));
+    }
+
+    #[test]
+    fn reader_disconnect_test() {
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<u32>(1);
+
+        assert!(buffer_writer.try_write(1).is_ok());
+
+        let writer_thread = std::thread::spawn(move || buffer_writer.write(2));
 
+        thread::sleep(Duration::from_millis(20));
+
+        drop(buffer_reader);
+
+        assert_eq!(writer_thread.join().unwrap(), Err(WriteError(2)));
+    }
+
+    #[test]
+    fn close_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(4);
+
+        assert!(buffer_writer.try_write(1).is_ok());
+
+        buffer_reader.close();
+
+        assert!(buffer_writer.is_closed());
+        assert_eq!(buffer_writer.try_write(2), Err(TryWriteError::Disconnected(2)));
+
+        assert_eq!(buffer_reader.try_read(), Ok(1));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Disconnected));
+    }
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn drop_remaining_elements_test() {
+        let drop_count = Arc::new(AtomicUsize::new(0));
+
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<DropCounter>(4);
+
+        assert!(buffer_writer.try_write(DropCounter(drop_count.clone())).is_ok());
+
+        drop(buffer_reader);
+
+        assert_eq!(drop_count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<DropCounter>(4);
+
+        drop(buffer_reader);
+
+        assert!(buffer_writer.try_write(DropCounter(drop_count.clone())).is_err());
+        assert_eq!(drop_count.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<DropCounter>(4);
+
+        assert!(buffer_writer.try_write(DropCounter(drop_count.clone())).is_ok());
+        assert!(buffer_writer.try_write(DropCounter(drop_count.clone())).is_ok());
+
+        drop(buffer_writer);
+        drop(buffer_reader);
+
+        assert_eq!(drop_count.load(std::sync::atomic::Ordering::Relaxed), 4);
+    }
+
+    struct PanicOnDrop(Arc<AtomicUsize>);
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+            panic!("dropping element");
+        }
+    }
+
+    #[test]
+    fn panicking_drop_test() {
+        let drop_count = Arc::new(AtomicUsize::new(0));
+
+        let (mut buffer_writer, buffer_reader) = create_ring_buffer::<Box<dyn Send>>(4);
+
+        assert!(buffer_writer.try_write(Box::new(DropCounter(drop_count.clone()))).is_ok());
+        assert!(buffer_writer.try_write(Box::new(PanicOnDrop(drop_count.clone()))).is_ok());
+        assert!(buffer_writer.try_write(Box::new(DropCounter(drop_count.clone()))).is_ok());
+        assert!(buffer_writer.try_write(Box::new(DropCounter(drop_count.clone()))).is_ok());
+
+        drop(buffer_writer);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(buffer_reader)));
+
+        assert!(result.is_err());
+        assert_eq!(drop_count.load(std::sync::atomic::Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn exact_capacity_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(12);
+
+        for round in 0..3 {
+            for idx in 0..12 {
+                assert!(buffer_writer.try_write(round * 12 + idx).is_ok());
+            }
+
+            assert_eq!(buffer_writer.size(), 12);
+            assert!(buffer_writer.try_write(0).is_err());
+
+            for idx in 0..12 {
+                assert_eq!(buffer_reader.try_read(), Ok(round * 12 + idx));
+            }
+        }
+    }
+
+    #[test]
+    fn compact_capacity_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_compact_ring_buffer::<u32>(3);
+
+        assert_eq!(buffer_writer.capacity(), 3);
+
+        for idx in 0..10 {
+            assert!(buffer_writer.try_write(idx).is_ok());
+            assert!(buffer_writer.try_write(idx + 100).is_ok());
+            assert!(buffer_writer.try_write(idx + 200).is_ok());
+            assert!(buffer_writer.try_write(0).is_err());
+
+            assert_eq!(buffer_reader.try_read(), Ok(idx));
+            assert_eq!(buffer_reader.try_read(), Ok(idx + 100));
+            assert_eq!(buffer_reader.try_read(), Ok(idx + 200));
+        }
+    }
+
+    #[repr(align(128))]
+    struct OverAligned(u8);
+
+    #[test]
+    fn over_aligned_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_compact_ring_buffer::<OverAligned>(3);
+
+        for idx in 0..5 {
+            assert!(buffer_writer.try_write(OverAligned(idx)).is_ok());
+            assert!(buffer_writer.try_write(OverAligned(idx + 1)).is_ok());
+
+            for element in buffer_reader.iter() {
+                assert_eq!(&*element as *const OverAligned as usize % 128, 0);
+            }
+
+            assert_eq!(buffer_reader.try_read().map(|v| v.0), Ok(idx));
+            assert_eq!(buffer_reader.try_read().map(|v| v.0), Ok(idx + 1));
+        }
+
+        let mut grant = buffer_writer.reserve(3);
+        let (first, second) = grant.as_mut_slices();
+
+        for slot in first.iter().chain(second.iter()) {
+            assert_eq!(slot.as_ptr() as usize % 128, 0);
+        }
+    }
+
+    #[test]
+    fn zero_sized_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<()>(3);
+
+        assert_eq!(buffer_writer.capacity(), 3);
+
+        for _ in 0..10 {
+            assert!(buffer_writer.try_write(()).is_ok());
+            assert!(buffer_writer.try_write(()).is_ok());
+            assert!(buffer_writer.try_write(()).is_ok());
+            assert!(buffer_writer.try_write(()).is_err());
+
+            assert_eq!(buffer_reader.size(), 3);
+            assert_eq!(buffer_reader.drain().count(), 3);
+            assert_eq!(buffer_reader.try_read(), Err(TryReadError::Empty));
+        }
     }
 }
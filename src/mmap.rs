@@ -0,0 +1,113 @@
+//! Memory mappings of files shared by the rings that live outside the heap.
+
+use std::io;
+use std::os::fd::{AsRawFd, BorrowedFd};
+use std::ptr::NonNull;
+
+/// Element types that can be stored in the rings in shared memory and in files.
+///
+/// Elements are copied in and out of the mapping bytewise and read back by another process or
+/// after a restart, so only the bytes of the value are carried over.
+///
+/// # Safety
+///
+/// Implementing types must be valid for any bit pattern and must not contain padding bytes,
+/// references, pointers or handles that only mean something within one process.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+/// Shared read/write mapping of the first `len` bytes of a file, unmapped on drop.
+pub(crate) struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The mapping is only a range of addresses, what lives in it is synchronized by its users.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    pub(crate) fn new(fd: BorrowedFd<'_>, len: usize) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Mapping {
+            ptr: NonNull::new(ptr as *mut u8).unwrap(),
+            len,
+        })
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Writes the whole mapping back to the file and waits until it is done.
+    #[cfg(feature = "persist")]
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.sync_range(0, self.len)
+    }
+
+    /// Writes the pages holding `len` bytes at `offset` back to the file and waits until it is
+    /// done.
+    #[cfg(feature = "persist")]
+    pub(crate) fn sync_range(&self, offset: usize, len: usize) -> io::Result<()> {
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+
+        let start = offset - offset % page_size;
+        let end = offset.saturating_add(len).min(self.len);
+
+        if start >= end {
+            return Ok(());
+        }
+
+        cvt(unsafe {
+            libc::msync(
+                self.ptr.as_ptr().add(start) as *mut libc::c_void,
+                end - start,
+                libc::MS_SYNC,
+            )
+        })
+        .map(drop)
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr.as_ptr() as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+pub(crate) fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+pub(crate) fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,362 @@
+//! Bounded multi producer, multi consumer queue.
+//!
+//! Works like the ring of [`create_mpsc_ring_buffer`](crate::create_mpsc_ring_buffer), with the
+//! slots stamped like described in [`crate::stamped`]. Readers claim positions with a
+//! compare-exchange on `rd_index` just like writers do on `wr_index`, and only the one that
+//! claimed a position touches its slot until it updates the stamp.
+
+use alloc::sync::Arc;
+
+use crate::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::error::{TryReadError, TryWriteError};
+use crate::stamped::StampedSlots;
+
+/// Bounded queue any number of threads can write to and read from.
+///
+/// The queue can be used through a shared reference, or split into cloneable [`MpmcWriter`]s
+/// and [`MpmcReader`]s that notice when the other side is gone.
+pub struct MpmcQueue<T: Sized> {
+    slots: StampedSlots<T>,
+
+    // Number of live handles of either side, the queue is closed once one of them drops to zero.
+    writers: AtomicUsize,
+    readers: AtomicUsize,
+    closed: AtomicBool,
+}
+
+/// Writing half of a queue, see [`create_mpmc_queue`]. Clone it to get more writers.
+pub struct MpmcWriter<T: Sized> {
+    queue: Arc<MpmcQueue<T>>,
+}
+
+/// Reading half of a queue, see [`create_mpmc_queue`]. Clone it to get more readers.
+pub struct MpmcReader<T: Sized> {
+    queue: Arc<MpmcQueue<T>>,
+}
+
+impl<T: Sized> MpmcQueue<T> {
+    /// Creates a queue that holds up to `buffer_capacity` elements.
+    pub fn new(buffer_capacity: usize) -> Self {
+        MpmcQueue {
+            slots: StampedSlots::new(buffer_capacity),
+            writers: AtomicUsize::new(0),
+            readers: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    /// Turns the queue into a writer and a reader, both of which can be cloned.
+    pub fn split(self) -> (MpmcWriter<T>, MpmcReader<T>) {
+        self.writers.store(1, Ordering::Relaxed);
+        self.readers.store(1, Ordering::Relaxed);
+
+        let queue = Arc::new(self);
+
+        (
+            MpmcWriter {
+                queue: queue.clone(),
+            },
+            MpmcReader { queue },
+        )
+    }
+
+    /// Number of claimed slots, including the ones writers or readers are still accessing.
+    pub fn size(&self) -> usize {
+        self.slots.size()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    /// Writes `value` if there is a free slot, otherwise returns it back in
+    /// [`TryWriteError::Full`].
+    pub fn try_write(&self, value: T) -> Result<(), TryWriteError<T>> {
+        if self.is_closed() {
+            return Err(TryWriteError::Disconnected(value));
+        }
+
+        self.slots.push(value).map_err(TryWriteError::Full)
+    }
+
+    /// Reads the oldest element, fails with [`TryReadError::Empty`] if there is none.
+    ///
+    /// An element whose writer claimed its slot but hasn't finished writing it yet is not
+    /// readable, elements written after it by other writers have to wait for it.
+    pub fn try_read(&self) -> Result<T, TryReadError> {
+        if let Some(v) = self.slots.pop() {
+            return Ok(v);
+        }
+
+        if !self.is_closed() {
+            return Err(TryReadError::Empty);
+        }
+
+        // The writers may have published more elements right before closing.
+        self.slots.pop().ok_or(TryReadError::Disconnected)
+    }
+}
+
+impl<T: Sized> MpmcWriter<T> {
+    /// Number of claimed slots, see [`MpmcQueue::size`].
+    pub fn size(&self) -> usize {
+        self.queue.size()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Returns true once all readers have been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    /// Writes `value` if there is a free slot. Fails with [`TryWriteError::Disconnected`] once
+    /// all readers have been dropped.
+    pub fn try_write(&self, value: T) -> Result<(), TryWriteError<T>> {
+        self.queue.try_write(value)
+    }
+}
+
+impl<T: Sized> Clone for MpmcWriter<T> {
+    fn clone(&self) -> Self {
+        self.queue.writers.fetch_add(1, Ordering::Relaxed);
+
+        MpmcWriter {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Drop for MpmcWriter<T> {
+    fn drop(&mut self) {
+        if self.queue.writers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.queue.close();
+        }
+    }
+}
+
+impl<T: Sized> MpmcReader<T> {
+    /// Number of claimed slots, see [`MpmcQueue::size`].
+    pub fn size(&self) -> usize {
+        self.queue.size()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity()
+    }
+
+    /// Returns true once all writers have been dropped. There may still be elements left to
+    /// read.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
+    /// Reads the oldest element. Fails with [`TryReadError::Disconnected`] once the queue is
+    /// empty and all writers have been dropped.
+    pub fn try_read(&self) -> Result<T, TryReadError> {
+        self.queue.try_read()
+    }
+}
+
+/// Yields elements until the queue is empty, see [`MpmcReader::try_read`].
+impl<T: Sized> Iterator for MpmcReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.try_read().ok()
+    }
+}
+
+impl<T: Sized> Clone for MpmcReader<T> {
+    fn clone(&self) -> Self {
+        self.queue.readers.fetch_add(1, Ordering::Relaxed);
+
+        MpmcReader {
+            queue: self.queue.clone(),
+        }
+    }
+}
+
+impl<T> Drop for MpmcReader<T> {
+    fn drop(&mut self) {
+        if self.queue.readers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.queue.close();
+
+            while self.queue.try_read().is_ok() {}
+        }
+    }
+}
+
+/// Creates a queue that holds up to `buffer_capacity` elements and splits it into a writer and
+/// a reader, see [`MpmcQueue::split`].
+pub fn create_mpmc_queue<T: Sized>(buffer_capacity: usize) -> (MpmcWriter<T>, MpmcReader<T>) {
+    MpmcQueue::new(buffer_capacity).split()
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    use super::{create_mpmc_queue, MpmcQueue};
+    use crate::{TryReadError, TryWriteError};
+
+    #[test]
+    fn mpmc_basic_test() {
+        let (buffer_writer, buffer_reader) = create_mpmc_queue::<String>(3);
+
+        let second_reader = buffer_reader.clone();
+
+        for idx in 0..3 {
+            assert!(buffer_writer.try_write(idx.to_string()).is_ok());
+        }
+
+        assert_eq!(
+            buffer_writer.try_write(String::from("x")),
+            Err(TryWriteError::Full(String::from("x")))
+        );
+
+        assert_eq!(buffer_reader.try_read().as_deref(), Ok("0"));
+        assert_eq!(second_reader.try_read().as_deref(), Ok("1"));
+
+        drop(buffer_writer);
+
+        assert!(second_reader.is_closed());
+        assert_eq!(buffer_reader.try_read().as_deref(), Ok("2"));
+        assert_eq!(second_reader.try_read(), Err(TryReadError::Disconnected));
+
+        let (buffer_writer, buffer_reader) = create_mpmc_queue::<String>(3);
+
+        assert!(buffer_writer.try_write(String::from("dropped")).is_ok());
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            buffer_writer.try_write(String::from("x")),
+            Err(TryWriteError::Disconnected(String::from("x")))
+        );
+    }
+
+    /// Runs a pseudo random sequence of operations against the queue and a `VecDeque` holding
+    /// the expected contents.
+    #[test]
+    fn mpmc_model_test() {
+        for capacity in 1..6 {
+            let queue = MpmcQueue::<u32>::new(capacity);
+            let mut model = VecDeque::new();
+
+            let mut rng = 0x2545_f491_u32;
+
+            for value in 0..2_000 {
+                rng ^= rng << 13;
+                rng ^= rng >> 17;
+                rng ^= rng << 5;
+
+                if rng % 5 < 3 {
+                    let expected = if model.len() < capacity {
+                        model.push_back(value);
+
+                        Ok(())
+                    } else {
+                        Err(TryWriteError::Full(value))
+                    };
+
+                    assert_eq!(queue.try_write(value), expected);
+                } else {
+                    assert_eq!(queue.try_read().ok(), model.pop_front());
+                }
+
+                assert_eq!(queue.size(), model.len());
+            }
+        }
+    }
+
+    /// Moves the same elements through the queue and through a `Mutex<VecDeque>` per writer.
+    /// Writers append to their model before writing, readers check every element against the
+    /// front of its writer's model, which holds both the order of each writer and the counts.
+    #[test]
+    fn mpmc_threaded_test() {
+        const WRITERS: usize = 4;
+        const READERS: usize = 4;
+
+        let num_elements = if cfg!(miri) { 100 } else { 10_000 };
+
+        let (buffer_writer, buffer_reader) = create_mpmc_queue::<(usize, u32)>(8);
+
+        let models = Mutex::new([(); WRITERS].map(|()| VecDeque::new()));
+
+        let received = std::thread::scope(|s| {
+            for writer_idx in 0..WRITERS {
+                let buffer_writer = buffer_writer.clone();
+                let models = &models;
+
+                s.spawn(move || {
+                    for idx in 0..num_elements {
+                        models.lock().unwrap()[writer_idx].push_back(idx);
+
+                        while buffer_writer.try_write((writer_idx, idx)).is_err() {
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+
+            drop(buffer_writer);
+
+            let reader_threads = (0..READERS)
+                .map(|_| {
+                    let buffer_reader = buffer_reader.clone();
+                    let models = &models;
+
+                    s.spawn(move || {
+                        let mut received = [0; WRITERS];
+
+                        loop {
+                            // Reading with the lock held orders the reads of all readers, so
+                            // each element has to be the oldest one left of its writer.
+                            let mut models = models.lock().unwrap();
+
+                            match buffer_reader.try_read() {
+                                Ok((writer_idx, idx)) => {
+                                    assert_eq!(models[writer_idx].pop_front(), Some(idx));
+
+                                    received[writer_idx] += 1;
+                                }
+                                Err(TryReadError::Empty) => {
+                                    drop(models);
+
+                                    std::thread::yield_now();
+                                }
+                                Err(TryReadError::Disconnected) => return received,
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            reader_threads
+                .into_iter()
+                .map(|reader_thread| reader_thread.join().unwrap())
+                .fold([0; WRITERS], |mut total, received| {
+                    for (total, received) in total.iter_mut().zip(received) {
+                        *total += received;
+                    }
+
+                    total
+                })
+        });
+
+        assert_eq!(received, [num_elements; WRITERS]);
+        assert!(models.into_inner().unwrap().iter().all(VecDeque::is_empty));
+    }
+}
@@ -0,0 +1,364 @@
+//! Multi producer, single consumer ring buffer.
+//!
+//! The slots are stamped like described in [`crate::stamped`], the single reader frees them
+//! without a compare-exchange.
+
+use alloc::sync::Arc;
+use core::ops::Deref;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+// Loom only replaces the atomics of this ring, the others need `const` constructors.
+#[cfg(not(loom))]
+use crate::atomic::{AtomicBool, AtomicUsize, Ordering};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+#[cfg(feature = "std")]
+use crate::error::{DisconnectedError, ReadTimeoutError};
+use crate::error::{TryReadError, TryWriteError};
+use crate::stamped::StampedSlots;
+#[cfg(feature = "std")]
+use crate::wait::WaitSlot;
+
+struct MpscState<T: Sized> {
+    slots: StampedSlots<T>,
+
+    // Number of live writers, the ring is closed once the last one is dropped.
+    writers: AtomicUsize,
+    closed: AtomicBool,
+
+    #[cfg(feature = "std")]
+    rd_waiter: WaitSlot,
+}
+
+/// Writing half of a ring created by [`create_mpsc_ring_buffer`]. Clone it to get more writers.
+pub struct MpscWriter<T: Sized> {
+    shared_state: Arc<MpscState<T>>,
+}
+
+/// Reading half of a ring created by [`create_mpsc_ring_buffer`].
+pub struct MpscReader<T: Sized> {
+    shared_state: Arc<MpscState<T>>,
+}
+
+impl<T: Sized> MpscState<T> {
+    fn size(&self) -> usize {
+        self.slots.size()
+    }
+
+    fn capacity(&self) -> usize {
+        self.slots.capacity()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+
+        #[cfg(feature = "std")]
+        self.rd_waiter.notify();
+    }
+}
+
+impl<T: Sized> MpscWriter<T> {
+    /// Number of claimed slots, including the ones writers are still writing to.
+    pub fn size(&self) -> usize {
+        self.shared_state.size()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity()
+    }
+
+    /// Returns true once the reader has been dropped or closed the ring.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    pub fn try_write(&self, value: T) -> Result<(), TryWriteError<T>> {
+        let state = self.shared_state.deref();
+
+        if state.is_closed() {
+            return Err(TryWriteError::Disconnected(value));
+        }
+
+        state.slots.push(value).map_err(TryWriteError::Full)?;
+
+        #[cfg(feature = "std")]
+        state.rd_waiter.notify();
+
+        Ok(())
+    }
+}
+
+impl<T: Sized> Clone for MpscWriter<T> {
+    fn clone(&self) -> Self {
+        self.shared_state.writers.fetch_add(1, Ordering::Relaxed);
+
+        MpscWriter {
+            shared_state: self.shared_state.clone(),
+        }
+    }
+}
+
+impl<T> Drop for MpscWriter<T> {
+    fn drop(&mut self) {
+        if self.shared_state.writers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared_state.close();
+        }
+    }
+}
+
+impl<T: Sized> MpscReader<T> {
+    pub fn size(&self) -> usize {
+        self.shared_state.size()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared_state.capacity()
+    }
+
+    /// Returns true once all writers have been dropped or the ring was closed.
+    /// There may still be elements left to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Closes the ring. The writers can't write any more elements but the ones already written
+    /// can still be read.
+    pub fn close(&mut self) {
+        self.shared_state.close();
+    }
+
+    pub fn try_read(&mut self) -> Result<T, TryReadError> {
+        let state = self.shared_state.deref();
+
+        // The reader is the only one taking elements, and `&mut self` keeps it on one thread.
+        if let Some(v) = unsafe { state.slots.pop_single() } {
+            return Ok(v);
+        }
+
+        if !state.is_closed() {
+            return Err(TryReadError::Empty);
+        }
+
+        // The writers may have published more elements right before closing.
+        unsafe { state.slots.pop_single() }.ok_or(TryReadError::Disconnected)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Sized> MpscReader<T> {
+    /// Reads the next element, blocking the current thread until one is available.
+    /// Fails once the ring is empty and disconnected.
+    pub fn read(&mut self) -> Result<T, DisconnectedError> {
+        self.read_until(None).map_err(|_| DisconnectedError)
+    }
+
+    /// Like [`MpscReader::read`] but gives up after `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<T, ReadTimeoutError> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
+
+    fn read_until(&mut self, deadline: Option<Instant>) -> Result<T, ReadTimeoutError> {
+        loop {
+            match self.try_read() {
+                Ok(v) => return Ok(v),
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => return Err(ReadTimeoutError::Disconnected),
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(ReadTimeoutError::Timeout);
+            }
+
+            self.shared_state.rd_waiter.prepare_wait();
+
+            match self.try_read() {
+                Ok(v) => {
+                    self.shared_state.rd_waiter.cancel_wait();
+
+                    return Ok(v);
+                }
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => {
+                    self.shared_state.rd_waiter.cancel_wait();
+
+                    return Err(ReadTimeoutError::Disconnected);
+                }
+            }
+
+            self.shared_state.rd_waiter.park(deadline);
+        }
+    }
+}
+
+/// Yields elements until the ring is empty, see [`MpscReader::try_read`].
+impl<T: Sized> Iterator for MpscReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.try_read().ok()
+    }
+}
+
+impl<T> Drop for MpscReader<T> {
+    fn drop(&mut self) {
+        self.close();
+
+        while self.try_read().is_ok() {}
+    }
+}
+
+/// Creates a ring buffer that holds up to `buffer_capacity` elements written by any number of
+/// [`MpscWriter`]s.
+pub fn create_mpsc_ring_buffer<T: Sized>(buffer_capacity: usize) -> (MpscWriter<T>, MpscReader<T>) {
+    let shared_state = Arc::new(MpscState {
+        slots: StampedSlots::new(buffer_capacity),
+        writers: AtomicUsize::new(1),
+        closed: AtomicBool::new(false),
+        #[cfg(feature = "std")]
+        rd_waiter: WaitSlot::new(),
+    });
+
+    (
+        MpscWriter {
+            shared_state: shared_state.clone(),
+        },
+        MpscReader { shared_state },
+    )
+}
+
+#[cfg(all(test, not(loom), feature = "std"))]
+mod tests {
+    use super::create_mpsc_ring_buffer;
+    use crate::{TryReadError, TryWriteError};
+
+    #[test]
+    fn mpsc_basic_test() {
+        let (buffer_writer, mut buffer_reader) = create_mpsc_ring_buffer::<u32>(3);
+
+        let second_writer = buffer_writer.clone();
+
+        for round in 0..4 {
+            assert!(buffer_writer.try_write(round).is_ok());
+            assert!(second_writer.try_write(round + 100).is_ok());
+            assert!(buffer_writer.try_write(round + 200).is_ok());
+            assert_eq!(second_writer.try_write(0), Err(TryWriteError::Full(0)));
+
+            assert_eq!(buffer_reader.size(), 3);
+            assert_eq!(
+                buffer_reader.by_ref().collect::<Vec<_>>(),
+                vec![round, round + 100, round + 200]
+            );
+        }
+
+        drop(buffer_writer);
+
+        assert!(!buffer_reader.is_closed());
+        assert!(second_writer.try_write(7).is_ok());
+
+        drop(second_writer);
+
+        assert_eq!(buffer_reader.try_read(), Ok(7));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Disconnected));
+    }
+
+    #[test]
+    fn mpsc_threaded_test() {
+        let (buffer_writer, mut buffer_reader) = create_mpsc_ring_buffer::<(usize, u32)>(8);
+
+        let num_elements = if cfg!(miri) { 100 } else { 10_000 };
+
+        let writer_threads = (0..4)
+            .map(|writer_idx| {
+                let buffer_writer = buffer_writer.clone();
+
+                std::thread::spawn(move || {
+                    for idx in 0..num_elements {
+                        while buffer_writer.try_write((writer_idx, idx)).is_err() {
+                            std::thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        drop(buffer_writer);
+
+        let mut next = [0; 4];
+
+        while let Ok((writer_idx, idx)) = buffer_reader.read() {
+            assert_eq!(next[writer_idx], idx);
+
+            next[writer_idx] += 1;
+        }
+
+        assert_eq!(next, [num_elements; 4]);
+
+        for writer_thread in writer_threads {
+            writer_thread.join().unwrap();
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use loom::thread;
+
+    use super::create_mpsc_ring_buffer;
+
+    #[test]
+    fn concurrent_producers_test() {
+        loom::model(|| {
+            let (buffer_writer, mut buffer_reader) = create_mpsc_ring_buffer::<u32>(2);
+
+            let writer_threads = [1, 2].map(|value| {
+                let buffer_writer = buffer_writer.clone();
+
+                thread::spawn(move || {
+                    buffer_writer.try_write(value).unwrap();
+                })
+            });
+
+            drop(buffer_writer);
+
+            let mut received = 0;
+
+            while received != 3 {
+                match buffer_reader.try_read() {
+                    Ok(v) => received += v,
+                    Err(_) => thread::yield_now(),
+                }
+            }
+
+            for writer_thread in writer_threads {
+                writer_thread.join().unwrap();
+            }
+        });
+    }
+
+    #[test]
+    fn full_ring_test() {
+        loom::model(|| {
+            let (buffer_writer, mut buffer_reader) = create_mpsc_ring_buffer::<u32>(1);
+
+            let second_writer = buffer_writer.clone();
+
+            let writer_thread = thread::spawn(move || second_writer.try_write(2).is_ok());
+
+            let first_written = buffer_writer.try_write(1).is_ok();
+            let second_written = writer_thread.join().unwrap();
+
+            // Exactly one of them got the slot.
+            assert!(first_written != second_written);
+
+            let expected = if first_written { 1 } else { 2 };
+
+            assert_eq!(buffer_reader.try_read(), Ok(expected));
+        });
+    }
+}
@@ -0,0 +1,386 @@
+//! Non-consuming access to the readable elements of a [`BufferReader`].
+//!
+//! Everything here works on a snapshot of `rd_index`/`wr_index`. The writer never touches slots
+//! between the two, so the elements stay in place while the reader looks at them. Rings that
+//! overwrite the oldest element keep the read index claimed while a [`PeekRef`], [`PeekMut`] or
+//! [`Iter`] is alive, the writer drops new elements instead of evicting in the meantime.
+
+use core::fmt;
+use core::hint::spin_loop;
+use core::iter::FusedIterator;
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+
+use crate::atomic::Ordering;
+use crate::{BufferReader, FullPolicy};
+
+/// Value of `BufferReader::borrows` while the first borrow claims `rd_index` or the last one
+/// releases it. Other borrows wait for that to finish, so none of them can get ahead of the
+/// claim or see it released underneath them.
+const BORROWS_LOCKED: usize = usize::MAX;
+
+impl<T: Sized> BufferReader<T> {
+    /// Returns a reference to the next element without consuming it.
+    pub fn peek(&self) -> Option<PeekRef<'_, T>> {
+        self.get(0)
+    }
+
+    /// Returns a mutable reference to the next element without consuming it.
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T>> {
+        let (borrow, slot) = self.readable_slot(0)?;
+
+        Some(PeekMut {
+            _borrow: borrow,
+            slot,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Returns a reference to the `index`-th unread element.
+    pub fn get(&self, index: usize) -> Option<PeekRef<'_, T>> {
+        let (borrow, slot) = self.readable_slot(index)?;
+
+        Some(PeekRef {
+            _borrow: borrow,
+            slot,
+        })
+    }
+
+    /// Iterates over the elements that are readable right now without consuming them.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let state = self.shared_state.deref();
+
+        let mut iter = Iter {
+            reader: self,
+            borrow: None,
+            index: 0,
+            remaining: 0,
+        };
+
+        if state.used_slots(
+            state.load_rd_index(Ordering::Acquire),
+            state.wr_index.load(Ordering::Acquire),
+        ) == 0
+        {
+            return iter;
+        }
+
+        let borrow = SlotBorrow::new(self);
+
+        iter.index = borrow.index;
+        iter.remaining = state.used_slots(borrow.index, state.wr_index.load(Ordering::Acquire));
+
+        if iter.remaining > 0 {
+            iter.borrow = Some(borrow);
+        }
+
+        iter
+    }
+
+    /// Reads the next element only if `predicate` returns true for it.
+    pub fn pop_if<F: FnOnce(&T) -> bool>(&mut self, predicate: F) -> Option<T> {
+        if !predicate(&*self.peek()?) {
+            return None;
+        }
+
+        self.try_read().ok()
+    }
+
+    fn readable_slot(&self, index: usize) -> Option<(SlotBorrow<'_, T>, *mut T)> {
+        let state = self.shared_state.deref();
+
+        // Checked before claiming the read index, a lookup that fails leaves it alone.
+        if index
+            >= state.used_slots(
+                state.load_rd_index(Ordering::Acquire),
+                state.wr_index.load(Ordering::Acquire),
+            )
+        {
+            return None;
+        }
+
+        let borrow = SlotBorrow::new(self);
+
+        // The writer may have evicted elements before the claim.
+        if index >= state.used_slots(borrow.index, state.wr_index.load(Ordering::Acquire)) {
+            return None;
+        }
+
+        let slot = state.slot_ptr(borrow.index + index as u64);
+
+        Some((borrow, slot))
+    }
+
+    /// Counts a new borrow of the readable slots and returns the read index they start at. Only
+    /// the first of several live borrows claims `rd_index`.
+    fn borrow_slots(&self) -> u64 {
+        let state = self.shared_state.deref();
+
+        if state.policy != FullPolicy::OverwriteOldest {
+            return state.claim_rd_index();
+        }
+
+        loop {
+            match self.borrows.load(Ordering::Acquire) {
+                BORROWS_LOCKED => spin_loop(),
+                0 => {
+                    if self
+                        .borrows
+                        .compare_exchange_weak(
+                            0,
+                            BORROWS_LOCKED,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_ok()
+                    {
+                        let cur_read_idx = state.claim_rd_index();
+
+                        self.borrows.store(1, Ordering::Release);
+
+                        return cur_read_idx;
+                    }
+                }
+                count => {
+                    if self
+                        .borrows
+                        .compare_exchange_weak(
+                            count,
+                            count + 1,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_ok()
+                    {
+                        // Neither side can move the claimed index.
+                        return state.load_rd_index(Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ends a borrow counted by [`BufferReader::borrow_slots`], the last one releases the claim.
+    fn unborrow_slots(&self, index: u64) {
+        let state = self.shared_state.deref();
+
+        if state.policy != FullPolicy::OverwriteOldest {
+            return;
+        }
+
+        loop {
+            match self.borrows.load(Ordering::Acquire) {
+                BORROWS_LOCKED => spin_loop(),
+                1 => {
+                    if self
+                        .borrows
+                        .compare_exchange_weak(
+                            1,
+                            BORROWS_LOCKED,
+                            Ordering::Acquire,
+                            Ordering::Relaxed,
+                        )
+                        .is_ok()
+                    {
+                        state.release_rd_index(index);
+
+                        self.borrows.store(0, Ordering::Release);
+
+                        return;
+                    }
+                }
+                count => {
+                    if self
+                        .borrows
+                        .compare_exchange_weak(
+                            count,
+                            count - 1,
+                            Ordering::Release,
+                            Ordering::Relaxed,
+                        )
+                        .is_ok()
+                    {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One counted borrow of the readable slots, ended on drop.
+struct SlotBorrow<'a, T: Sized> {
+    reader: &'a BufferReader<T>,
+    index: u64,
+}
+
+impl<'a, T: Sized> SlotBorrow<'a, T> {
+    fn new(reader: &'a BufferReader<T>) -> Self {
+        SlotBorrow {
+            reader,
+            index: reader.borrow_slots(),
+        }
+    }
+}
+
+impl<T: Sized> Drop for SlotBorrow<'_, T> {
+    fn drop(&mut self) {
+        self.reader.unborrow_slots(self.index);
+    }
+}
+
+/// Reference to an unread element returned by [`BufferReader::peek`] and
+/// [`BufferReader::get`]. The writer can't evict the element while it is alive.
+pub struct PeekRef<'a, T: Sized> {
+    _borrow: SlotBorrow<'a, T>,
+    slot: *mut T,
+}
+
+impl<T: Sized + fmt::Debug> fmt::Debug for PeekRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Sized> Deref for PeekRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.slot }
+    }
+}
+
+/// Mutable reference to the next element returned by [`BufferReader::peek_mut`]. The writer
+/// can't evict the element while it is alive.
+pub struct PeekMut<'a, T: Sized> {
+    _borrow: SlotBorrow<'a, T>,
+    slot: *mut T,
+    _marker: PhantomData<&'a mut T>,
+}
+
+impl<T: Sized + fmt::Debug> fmt::Debug for PeekMut<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Sized> Deref for PeekMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.slot }
+    }
+}
+
+impl<T: Sized> DerefMut for PeekMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.slot }
+    }
+}
+
+impl<'a, T: Sized> IntoIterator for &'a BufferReader<T> {
+    type Item = PeekRef<'a, T>;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// Iterator returned by [`BufferReader::iter`]. Every element is handed out as a [`PeekRef`],
+/// which keeps it in place after the iterator is gone.
+pub struct Iter<'a, T: Sized> {
+    reader: &'a BufferReader<T>,
+    // Keeps the elements still to be visited in place, `None` if there are none.
+    borrow: Option<SlotBorrow<'a, T>>,
+    index: u64,
+    remaining: usize,
+}
+
+impl<'a, T: Sized> Iterator for Iter<'a, T> {
+    type Item = PeekRef<'a, T>;
+
+    fn next(&mut self) -> Option<PeekRef<'a, T>> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let slot = self.reader.shared_state.slot_ptr(self.index);
+
+        self.index += 1;
+        self.remaining -= 1;
+
+        Some(PeekRef {
+            _borrow: SlotBorrow::new(self.reader),
+            slot,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T: Sized> ExactSizeIterator for Iter<'_, T> {}
+
+impl<T: Sized> FusedIterator for Iter<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{create_ring_buffer, create_ring_buffer_with_policy, FullPolicy};
+
+    #[test]
+    fn peek_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<String>(4);
+
+        assert_eq!(buffer_reader.peek().as_deref(), None);
+
+        assert!(buffer_writer.try_write(String::from("header")).is_ok());
+        assert!(buffer_writer.try_write(String::from("body")).is_ok());
+
+        assert_eq!(buffer_reader.peek().as_deref().map(String::as_str), Some("header"));
+        assert_eq!(buffer_reader.get(1).as_deref().map(String::as_str), Some("body"));
+        assert_eq!(buffer_reader.get(2).as_deref(), None);
+
+        buffer_reader.peek_mut().unwrap().push('!');
+
+        assert_eq!(buffer_reader.pop_if(|s| s.is_empty()), None);
+        assert_eq!(
+            buffer_reader.pop_if(|s| s == "header!").as_deref(),
+            Some("header!")
+        );
+        assert_eq!(buffer_reader.peek().as_deref().map(String::as_str), Some("body"));
+    }
+
+    #[test]
+    fn iter_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(4);
+
+        assert_eq!(buffer_writer.write_slice(&[1, 2, 3, 4]), Ok(4));
+        assert_eq!(buffer_reader.try_read(), Ok(1));
+        assert_eq!(buffer_writer.write_slice(&[5]), Ok(1));
+
+        // The readable elements wrap around the end of the storage.
+        assert_eq!(buffer_reader.iter().len(), 4);
+        assert_eq!(
+            buffer_reader.iter().map(|v| *v).collect::<Vec<_>>(),
+            vec![2, 3, 4, 5]
+        );
+        assert_eq!((&buffer_reader).into_iter().map(|v| *v).sum::<u32>(), 14);
+
+        assert_eq!(buffer_reader.size(), 4);
+    }
+
+    #[test]
+    fn get_out_of_range_test() {
+        let (mut buffer_writer, buffer_reader) =
+            create_ring_buffer_with_policy::<u32>(2, FullPolicy::OverwriteOldest);
+
+        assert_eq!(buffer_writer.push(1), Ok(None));
+        assert_eq!(buffer_reader.get(1).as_deref(), None);
+
+        // The failed lookup doesn't keep the writer from evicting.
+        assert_eq!(buffer_writer.push(2), Ok(None));
+        assert_eq!(buffer_writer.push(3), Ok(Some(1)));
+    }
+}
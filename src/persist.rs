@@ -0,0 +1,899 @@
+//! Ring buffer kept in a memory mapped file, so buffered elements survive a restart.
+//!
+//! [`open_persistent_ring`] maps the file, creating it on first use, and hands out a writer and
+//! a reader for use within one process. Like in shared memory the file starts with a `repr(C)`
+//! header followed by the free running indices and the slots. Every slot holds a record of the
+//! element, its position in the ring and a checksum over both.
+//!
+//! How often the mapping is written back to the file is chosen with [`SyncPolicy`]. When the
+//! file is opened again the ring is recovered from what reached the disk: reading resumes at
+//! the durable read index and the records following it are taken for as long as their position
+//! and checksum are valid. Anything after the first torn or missing record is discarded.
+//! Elements read but not yet synced are read again, delivery is at least once.
+//!
+//! Elements are checksummed and copied bytewise, so `T` must implement [`Pod`].
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::os::fd::{AsFd, AsRawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::cache_padded::CachePadded;
+use crate::mmap::{cvt, invalid_data, Mapping, Pod};
+
+/// Identifies a file holding a ring.
+const PERSIST_MAGIC: u64 = u64::from_le_bytes(*b"ATRINGPF");
+
+/// Version of the layout of [`PersistState`] and the records following it.
+const PERSIST_LAYOUT_VERSION: u32 = 1;
+
+/// When the writes to the ring are written back to the file, see [`open_persistent_ring`].
+///
+/// Whatever the policy, [`PersistentWriter::sync`] and [`PersistentReader::sync`] write
+/// everything back right away and dropping a handle syncs it a last time.
+///
+/// Writes and reads that sync only take effect once the sync succeeded. If it fails the
+/// error is returned, the written elements aren't handed to the reader and the read ones
+/// stay in the ring, so the call can simply be retried.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum SyncPolicy {
+    /// Syncs every write and read, batches are synced once.
+    PerWrite,
+
+    /// Syncs once per [`PersistentWriter::write_batch`] and [`PersistentReader::read_batch`],
+    /// single elements are left to the next batch or explicit sync.
+    PerBatch,
+
+    /// Syncs the whole file from a background thread every time the given period has passed,
+    /// writes and reads don't sync. A failed background sync is reported by the next write,
+    /// read or sync of either handle before it does anything else.
+    Periodic(Duration),
+}
+
+#[repr(C)]
+struct PersistHeader {
+    magic: u64,
+    version: u32,
+    element_size: u32,
+    element_align: u32,
+    _reserved: u32,
+    capacity: u64,
+}
+
+/// Start of the file, the records follow at [`records_offset`].
+#[repr(C)]
+struct PersistState {
+    header: PersistHeader,
+
+    wr_index: CachePadded<AtomicU64>,
+    rd_index: CachePadded<AtomicU64>,
+}
+
+/// Content of a slot, `seq` is the index the element was written at.
+#[repr(C)]
+struct Record<T> {
+    seq: u64,
+    checksum: u64,
+    value: T,
+}
+
+/// Offset of the first record from the start of the file.
+fn records_offset<T>() -> usize {
+    size_of::<PersistState>().next_multiple_of(align_of::<Record<T>>())
+}
+
+fn file_len<T>(capacity: usize) -> io::Result<usize> {
+    capacity
+        .checked_mul(size_of::<Record<T>>())
+        .and_then(|len| len.checked_add(records_offset::<T>()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "ring buffer capacity overflow"))
+}
+
+/// 64-bit FNV-1a over the position and the bytes of the element.
+fn checksum<T: Pod>(seq: u64, value: &T) -> u64 {
+    let value_bytes =
+        unsafe { core::slice::from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+
+    seq.to_le_bytes()
+        .iter()
+        .chain(value_bytes)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        })
+}
+
+/// State shared between the writer and the reader.
+struct PersistentRing<T> {
+    map: Arc<Mapping>,
+
+    // Holds the lock keeping other handles from opening the file.
+    _file: File,
+
+    capacity: u64,
+    policy: SyncPolicy,
+
+    // Read index the writer may reuse slots up to, only moved forward once the read index in
+    // the file was synced as the policy asks for. Not stored in the file.
+    freed_index: CachePadded<AtomicU64>,
+
+    // Set once either handle is dropped, not stored in the file.
+    closed: AtomicBool,
+
+    // Only started for `SyncPolicy::Periodic`.
+    timer: Option<SyncTimer>,
+
+    // Makes the syncs of records and indices fail while set.
+    #[cfg(test)]
+    fail_syncs: AtomicBool,
+
+    _marker: PhantomData<T>,
+}
+
+// The records are only accessed through the indices of the ring, like the heap allocated rings.
+unsafe impl<T: Pod + Send> Send for PersistentRing<T> {}
+unsafe impl<T: Pod + Send> Sync for PersistentRing<T> {}
+
+impl<T: Pod> PersistentRing<T> {
+    /// Locks and maps `file`, initializing it if it is empty and recovering the ring otherwise.
+    fn open(file: File, capacity: usize, policy: SyncPolicy) -> io::Result<Self> {
+        cvt(unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) })?;
+
+        let capacity = capacity.max(1);
+        let len = file_len::<T>(capacity)?;
+
+        let existing_len = file.metadata()?.len();
+
+        if existing_len == 0 {
+            file.set_len(len as u64)?;
+        } else if existing_len != len as u64 {
+            return Err(invalid_data(
+                "ring buffer capacity doesn't match the file size",
+            ));
+        }
+
+        let mut ring = PersistentRing {
+            map: Arc::new(Mapping::new(file.as_fd(), len)?),
+            _file: file,
+            capacity: capacity as u64,
+            policy,
+            freed_index: CachePadded(AtomicU64::new(0)),
+            closed: AtomicBool::new(false),
+            timer: None,
+            #[cfg(test)]
+            fail_syncs: AtomicBool::new(false),
+            _marker: PhantomData,
+        };
+
+        // A file without magic was never completely initialized, the ring in it is empty.
+        if ring.header().magic == 0 {
+            ring.init()?;
+        } else {
+            ring.check_header()?;
+            ring.recover()?;
+        }
+
+        ring.freed_index.store(
+            ring.state().rd_index.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+
+        if let SyncPolicy::Periodic(period) = policy {
+            ring.timer = Some(SyncTimer::spawn(ring.map.clone(), period)?);
+        }
+
+        Ok(ring)
+    }
+
+    fn init(&self) -> io::Result<()> {
+        unsafe {
+            core::ptr::write_bytes(self.map.as_ptr(), 0, file_len::<T>(self.capacity as usize)?);
+
+            let header = &mut (*(self.map.as_ptr() as *mut PersistState)).header;
+
+            header.version = PERSIST_LAYOUT_VERSION;
+            header.element_size = size_of::<T>() as u32;
+            header.element_align = align_of::<T>() as u32;
+            header.capacity = self.capacity;
+        }
+
+        // The magic only reaches the file after the rest of the header.
+        self.map.sync()?;
+
+        unsafe {
+            (*(self.map.as_ptr() as *mut PersistState)).header.magic = PERSIST_MAGIC;
+        }
+
+        self.map.sync()
+    }
+
+    fn check_header(&self) -> io::Result<()> {
+        let header = self.header();
+
+        if header.magic != PERSIST_MAGIC {
+            return Err(invalid_data("file doesn't hold a ring buffer"));
+        }
+
+        if header.version != PERSIST_LAYOUT_VERSION {
+            return Err(invalid_data("unsupported ring buffer layout version"));
+        }
+
+        if header.element_size as usize != size_of::<T>()
+            || header.element_align as usize != align_of::<T>()
+        {
+            return Err(invalid_data(
+                "ring buffer holds elements of a different type",
+            ));
+        }
+
+        if header.capacity != self.capacity {
+            return Err(invalid_data("ring buffer capacity doesn't match"));
+        }
+
+        Ok(())
+    }
+
+    /// Moves `wr_index` to the first invalid record after the durable `rd_index` and
+    /// invalidates the records after it, which must not be taken for new ones later.
+    fn recover(&self) -> io::Result<()> {
+        let state = self.state();
+
+        let cur_read_idx = state.rd_index.load(Ordering::Relaxed);
+        let end_idx = cur_read_idx + self.capacity;
+
+        let mut cur_write_idx = cur_read_idx;
+
+        while cur_write_idx < end_idx && self.is_valid(cur_write_idx) {
+            cur_write_idx += 1;
+        }
+
+        state.wr_index.store(cur_write_idx, Ordering::Relaxed);
+
+        let mut discarded = false;
+
+        for idx in cur_write_idx..end_idx {
+            let record = self.record_ptr(idx);
+
+            unsafe {
+                if (*record).seq >= cur_write_idx {
+                    (*record).seq = u64::MAX;
+                    (*record).checksum = 0;
+                    discarded = true;
+                }
+            }
+        }
+
+        if discarded {
+            self.map.sync()?;
+        }
+
+        Ok(())
+    }
+
+    fn is_valid(&self, index: u64) -> bool {
+        let record = unsafe { &*self.record_ptr(index) };
+
+        record.seq == index && record.checksum == checksum(index, &record.value)
+    }
+
+    fn header(&self) -> &PersistHeader {
+        &self.state().header
+    }
+
+    fn state(&self) -> &PersistState {
+        unsafe { &*(self.map.as_ptr() as *const PersistState) }
+    }
+
+    fn slot_of(&self, index: u64) -> usize {
+        (index % self.capacity) as usize
+    }
+
+    fn record_ptr(&self, index: u64) -> *mut Record<T> {
+        unsafe {
+            self.map
+                .as_ptr()
+                .add(records_offset::<T>())
+                .cast::<Record<T>>()
+                .add(self.slot_of(index))
+        }
+    }
+
+    fn used_slots(cur_read_idx: u64, cur_write_idx: u64) -> usize {
+        cur_write_idx.wrapping_sub(cur_read_idx) as usize
+    }
+
+    /// Syncs the records from `first_idx` up to `end_idx`. The write index isn't synced,
+    /// recovery finds the end of the ring from the records.
+    fn sync_records(&self, first_idx: u64, end_idx: u64) -> io::Result<()> {
+        #[cfg(test)]
+        self.check_fail_syncs()?;
+
+        let count = (end_idx - first_idx).min(self.capacity) as usize;
+        let first_slot = self.slot_of(first_idx);
+        let first_len = count.min(self.capacity as usize - first_slot);
+
+        let record_size = size_of::<Record<T>>();
+        let offset = records_offset::<T>();
+
+        self.map
+            .sync_range(offset + first_slot * record_size, first_len * record_size)?;
+
+        if first_len < count {
+            self.map
+                .sync_range(offset, (count - first_len) * record_size)?;
+        }
+
+        Ok(())
+    }
+
+    fn sync_indices(&self) -> io::Result<()> {
+        #[cfg(test)]
+        self.check_fail_syncs()?;
+
+        self.map.sync_range(0, size_of::<PersistState>())
+    }
+
+    #[cfg(test)]
+    fn check_fail_syncs(&self) -> io::Result<()> {
+        match self.fail_syncs.load(Ordering::Relaxed) {
+            true => Err(io::Error::from_raw_os_error(libc::EIO)),
+            false => Ok(()),
+        }
+    }
+
+    /// Returns the error of a failed background sync that wasn't reported yet.
+    fn sync_error(&self) -> io::Result<()> {
+        match &self.timer {
+            Some(timer) => timer.take_error(),
+            None => Ok(()),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+/// Background thread syncing the whole file every period for [`SyncPolicy::Periodic`].
+struct SyncTimer {
+    // Dropped with the ring, which wakes the thread up and ends it.
+    stop: Option<mpsc::Sender<()>>,
+
+    // Joined on drop, the mapping it holds keeps the file locked.
+    thread: Option<JoinHandle<()>>,
+
+    // First error of a background sync, until a handle reports it.
+    error: Arc<Mutex<Option<io::Error>>>,
+}
+
+impl SyncTimer {
+    fn spawn(map: Arc<Mapping>, period: Duration) -> io::Result<Self> {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let error = Arc::new(Mutex::new(None));
+
+        let thread_error = error.clone();
+
+        let thread = thread::Builder::new()
+            .name("persistent-ring-sync".into())
+            .spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(period) {
+                    if let Err(e) = map.sync() {
+                        thread_error.lock().unwrap().get_or_insert(e);
+                    }
+                }
+            })?;
+
+        Ok(SyncTimer {
+            stop: Some(stop),
+            thread: Some(thread),
+            error,
+        })
+    }
+
+    fn take_error(&self) -> io::Result<()> {
+        match self.error.lock().unwrap().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SyncTimer {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Writing half of a persistent ring, see [`open_persistent_ring`].
+pub struct PersistentWriter<T: Pod> {
+    ring: Arc<PersistentRing<T>>,
+
+    // Last value of `rd_index` seen by the writer, only reloaded once the ring looks full.
+    cached_rd_index: u64,
+}
+
+/// Reading half of a persistent ring, see [`open_persistent_ring`].
+pub struct PersistentReader<T: Pod> {
+    ring: Arc<PersistentRing<T>>,
+
+    // Last value of `wr_index` seen by the reader, only reloaded once the ring looks empty.
+    cached_wr_index: u64,
+}
+
+impl<T: Pod> PersistentWriter<T> {
+    pub fn size(&self) -> usize {
+        PersistentRing::<T>::used_slots(
+            self.ring.freed_index.load(Ordering::Acquire),
+            self.ring.state().wr_index.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity as usize
+    }
+
+    /// Returns true once the reader has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.ring.is_closed()
+    }
+
+    /// Writes the ring back to the file and waits until it is done.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.ring.sync_error()?;
+        self.ring.map.sync()
+    }
+
+    /// Appends `value` and syncs it according to the [`SyncPolicy`]. Fails with
+    /// [`io::ErrorKind::WouldBlock`] if the ring is full and with [`io::ErrorKind::BrokenPipe`]
+    /// once the reader is gone.
+    pub fn try_write(&mut self, value: T) -> io::Result<()> {
+        let sync = self.ring.policy == SyncPolicy::PerWrite;
+
+        self.write_records(core::slice::from_ref(&value), sync)
+            .map(drop)
+    }
+
+    /// Appends as many elements of `values` as fit and syncs them according to the
+    /// [`SyncPolicy`]. Returns the number of elements written, fails like
+    /// [`try_write`](PersistentWriter::try_write) if none could be written.
+    pub fn write_batch(&mut self, values: &[T]) -> io::Result<usize> {
+        if values.is_empty() {
+            return self.ring.sync_error().map(|_| 0);
+        }
+
+        let sync = matches!(
+            self.ring.policy,
+            SyncPolicy::PerWrite | SyncPolicy::PerBatch
+        );
+
+        self.write_records(values, sync)
+    }
+
+    /// Stores as many of `values` as fit, syncs them if asked to and then publishes them.
+    fn write_records(&mut self, values: &[T], sync: bool) -> io::Result<usize> {
+        self.ring.sync_error()?;
+
+        if self.ring.is_closed() {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        let state = self.ring.state();
+
+        let first_idx = state.wr_index.load(Ordering::Relaxed);
+
+        let mut free_slots =
+            self.capacity() - PersistentRing::<T>::used_slots(self.cached_rd_index, first_idx);
+
+        if free_slots < values.len() {
+            self.cached_rd_index = self.ring.freed_index.load(Ordering::Acquire);
+
+            free_slots =
+                self.capacity() - PersistentRing::<T>::used_slots(self.cached_rd_index, first_idx);
+        }
+
+        if free_slots == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let count = values.len().min(free_slots) as u64;
+
+        for (idx, &value) in (first_idx..).zip(&values[..count as usize]) {
+            unsafe {
+                core::ptr::write(
+                    self.ring.record_ptr(idx),
+                    Record {
+                        seq: idx,
+                        checksum: checksum(idx, &value),
+                        value,
+                    },
+                );
+            }
+        }
+
+        // The reader only gets to see the records once they are durable.
+        if sync {
+            self.ring.sync_records(first_idx, first_idx + count)?;
+        }
+
+        state.wr_index.store(first_idx + count, Ordering::Release);
+
+        Ok(count as usize)
+    }
+}
+
+impl<T: Pod> Drop for PersistentWriter<T> {
+    fn drop(&mut self) {
+        self.ring.close();
+
+        let _ = self.ring.map.sync();
+    }
+}
+
+impl<T: Pod> PersistentReader<T> {
+    pub fn size(&self) -> usize {
+        let state = self.ring.state();
+
+        PersistentRing::<T>::used_slots(
+            state.rd_index.load(Ordering::Relaxed),
+            state.wr_index.load(Ordering::Acquire),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.capacity as usize
+    }
+
+    /// Returns true once the writer has been dropped. There may still be elements left to read.
+    pub fn is_closed(&self) -> bool {
+        self.ring.is_closed()
+    }
+
+    /// Writes the read index back to the file and waits until it is done.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.ring.sync_error()?;
+        self.ring.sync_indices()
+    }
+
+    /// Takes the oldest element and syncs the read index according to the [`SyncPolicy`].
+    /// Fails with [`io::ErrorKind::WouldBlock`] if the ring is empty, returns `None` once the
+    /// writer is gone and everything has been read.
+    pub fn try_read(&mut self) -> io::Result<Option<T>> {
+        // Any bit pattern is a valid `T`.
+        let mut value = [unsafe { core::mem::zeroed() }];
+
+        let sync = self.ring.policy == SyncPolicy::PerWrite;
+
+        match self.read_records(&mut value, sync)? {
+            0 => Ok(None),
+            _ => Ok(Some(value[0])),
+        }
+    }
+
+    /// Takes as many elements as fit into `buf` and syncs the read index according to the
+    /// [`SyncPolicy`]. Returns the number of elements read, `0` once the writer is gone and
+    /// everything has been read, fails like [`try_read`](PersistentReader::try_read) if the
+    /// ring is empty. Nothing is taken from the ring when it fails.
+    pub fn read_batch(&mut self, buf: &mut [T]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return self.ring.sync_error().map(|_| 0);
+        }
+
+        let sync = matches!(
+            self.ring.policy,
+            SyncPolicy::PerWrite | SyncPolicy::PerBatch
+        );
+
+        self.read_records(buf, sync)
+    }
+
+    /// Copies as many elements as fit into `buf`, syncs the read index if asked to and then
+    /// frees their slots for the writer.
+    fn read_records(&mut self, buf: &mut [T], sync: bool) -> io::Result<usize> {
+        self.ring.sync_error()?;
+
+        let state = self.ring.state();
+
+        let cur_read_idx = state.rd_index.load(Ordering::Relaxed);
+
+        if PersistentRing::<T>::used_slots(cur_read_idx, self.cached_wr_index) < buf.len() {
+            self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
+        }
+
+        if cur_read_idx == self.cached_wr_index {
+            if !self.ring.is_closed() {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            // The writer may have published more elements right before closing.
+            self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
+
+            if cur_read_idx == self.cached_wr_index {
+                return Ok(0);
+            }
+        }
+
+        let count =
+            PersistentRing::<T>::used_slots(cur_read_idx, self.cached_wr_index).min(buf.len());
+
+        for (idx, slot) in (cur_read_idx..).zip(&mut buf[..count]) {
+            *slot = unsafe { (*self.ring.record_ptr(idx)).value };
+        }
+
+        let end_idx = cur_read_idx + count as u64;
+
+        state.rd_index.store(end_idx, Ordering::Relaxed);
+
+        // The writer may only reuse the slots once the read index is durable.
+        if sync {
+            if let Err(e) = self.ring.sync_indices() {
+                state.rd_index.store(cur_read_idx, Ordering::Relaxed);
+
+                return Err(e);
+            }
+        }
+
+        self.ring.freed_index.store(end_idx, Ordering::Release);
+
+        Ok(count)
+    }
+}
+
+impl<T: Pod> Drop for PersistentReader<T> {
+    fn drop(&mut self) {
+        self.ring.close();
+
+        let _ = self.ring.sync_indices();
+    }
+}
+
+/// Opens the ring of up to `buffer_capacity` elements in the file at `path`, creating the file
+/// if it doesn't exist, and returns its writer and reader. Elements left in the file by an
+/// earlier run can be read right away.
+///
+/// Fails with [`io::ErrorKind::InvalidData`] if the file holds a ring of another type or
+/// capacity, and with [`io::ErrorKind::WouldBlock`] if the file is already open.
+pub fn open_persistent_ring<T: Pod>(
+    path: impl AsRef<Path>,
+    buffer_capacity: usize,
+    policy: SyncPolicy,
+) -> io::Result<(PersistentWriter<T>, PersistentReader<T>)> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+
+    let ring = Arc::new(PersistentRing::open(file, buffer_capacity, policy)?);
+
+    let state = ring.state();
+
+    let cached_rd_index = state.rd_index.load(Ordering::Relaxed);
+    let cached_wr_index = state.wr_index.load(Ordering::Relaxed);
+
+    Ok((
+        PersistentWriter {
+            ring: ring.clone(),
+            cached_rd_index,
+        },
+        PersistentReader {
+            ring,
+            cached_wr_index,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{self, Seek, SeekFrom, Write};
+    use std::mem::size_of;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::{open_persistent_ring, records_offset, Record, SyncPolicy};
+
+    /// Path of a file in the temporary directory, removed when dropped.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new() -> Self {
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+            TempPath(std::env::temp_dir().join(format!(
+                "atomic_ring_buffer_test_{}_{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn persist_reopen_test() {
+        let path = TempPath::new();
+
+        for policy in [
+            SyncPolicy::PerWrite,
+            SyncPolicy::PerBatch,
+            SyncPolicy::Periodic(Duration::from_millis(1)),
+        ] {
+            let (mut buffer_writer, mut buffer_reader) =
+                open_persistent_ring::<u64>(&path.0, 4, policy).unwrap();
+
+            assert_eq!(buffer_writer.write_batch(&[0, 1, 2, 3, 4]).unwrap(), 4);
+            assert_eq!(
+                buffer_writer.try_write(4).unwrap_err().kind(),
+                io::ErrorKind::WouldBlock
+            );
+            assert_eq!(buffer_reader.try_read().unwrap(), Some(0));
+
+            let mut buf = [0; 2];
+
+            assert_eq!(buffer_reader.read_batch(&mut buf).unwrap(), 2);
+            assert_eq!(buf, [1, 2]);
+
+            buffer_writer.try_write(4).unwrap();
+
+            drop(buffer_writer);
+            drop(buffer_reader);
+
+            // Resumes after the elements read before.
+            let (mut buffer_writer, mut buffer_reader) =
+                open_persistent_ring::<u64>(&path.0, 4, policy).unwrap();
+
+            assert_eq!(buffer_reader.size(), 2);
+
+            buffer_writer.write_batch(&[5, 6]).unwrap();
+
+            let mut buf = [0; 8];
+
+            assert_eq!(buffer_reader.read_batch(&mut buf).unwrap(), 4);
+            assert_eq!(buf[..4], [3, 4, 5, 6]);
+
+            drop(buffer_writer);
+
+            assert_eq!(buffer_reader.try_read().unwrap(), None);
+            assert_eq!(buffer_reader.read_batch(&mut buf).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn persist_torn_write_test() {
+        let path = TempPath::new();
+
+        {
+            let (mut buffer_writer, mut buffer_reader) =
+                open_persistent_ring::<u64>(&path.0, 8, SyncPolicy::PerBatch).unwrap();
+
+            buffer_writer
+                .write_batch(&[10, 11, 12, 13, 14, 15])
+                .unwrap();
+
+            assert_eq!(buffer_reader.try_read().unwrap(), Some(10));
+        }
+
+        // Tears the value of the record at index 3.
+        let mut file = OpenOptions::new().write(true).open(&path.0).unwrap();
+
+        file.seek(SeekFrom::Start(
+            (records_offset::<u64>() + 3 * size_of::<Record<u64>>() + 16) as u64,
+        ))
+        .unwrap();
+        file.write_all(&[0xff; 4]).unwrap();
+
+        drop(file);
+
+        {
+            let (mut buffer_writer, buffer_reader) =
+                open_persistent_ring::<u64>(&path.0, 8, SyncPolicy::PerBatch).unwrap();
+
+            assert_eq!(buffer_reader.size(), 2);
+
+            // The records after the torn one must not come back with the next recovery.
+            buffer_writer.try_write(20).unwrap();
+        }
+
+        let (buffer_writer, mut buffer_reader) =
+            open_persistent_ring::<u64>(&path.0, 8, SyncPolicy::PerBatch).unwrap();
+
+        drop(buffer_writer);
+
+        let mut buf = [0; 8];
+
+        assert_eq!(buffer_reader.read_batch(&mut buf).unwrap(), 3);
+        assert_eq!(buf[..3], [11, 12, 20]);
+    }
+
+    #[test]
+    fn persist_sync_error_test() {
+        let path = TempPath::new();
+
+        let (mut buffer_writer, mut buffer_reader) =
+            open_persistent_ring::<u64>(&path.0, 4, SyncPolicy::PerWrite).unwrap();
+
+        let ring = buffer_writer.ring.clone();
+        let fail_syncs = |fail| ring.fail_syncs.store(fail, Ordering::Relaxed);
+
+        // Failed writes don't reach the reader.
+        fail_syncs(true);
+
+        assert!(buffer_writer.try_write(1).is_err());
+        assert!(buffer_writer.write_batch(&[2, 3]).is_err());
+        assert_eq!(buffer_reader.size(), 0);
+
+        fail_syncs(false);
+
+        assert_eq!(buffer_writer.write_batch(&[1, 2, 3, 4]).unwrap(), 4);
+
+        // Failed reads leave the elements in the ring and keep the slots taken.
+        fail_syncs(true);
+
+        let mut buf = [0; 4];
+
+        assert!(buffer_reader.try_read().is_err());
+        assert!(buffer_reader.read_batch(&mut buf).is_err());
+        assert_eq!(buffer_reader.size(), 4);
+        assert_eq!(buffer_writer.size(), 4);
+
+        fail_syncs(false);
+
+        assert_eq!(buffer_reader.try_read().unwrap(), Some(1));
+        assert_eq!(buffer_reader.read_batch(&mut buf).unwrap(), 3);
+        assert_eq!(buf[..3], [2, 3, 4]);
+        assert_eq!(buffer_writer.size(), 0);
+    }
+
+    #[test]
+    fn persist_header_test() {
+        let path = TempPath::new();
+
+        let handles = open_persistent_ring::<u32>(&path.0, 4, SyncPolicy::PerWrite).unwrap();
+
+        assert_eq!(
+            open_persistent_ring::<u32>(&path.0, 4, SyncPolicy::PerWrite)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        drop(handles);
+
+        assert_eq!(
+            open_persistent_ring::<u32>(&path.0, 8, SyncPolicy::PerWrite)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            open_persistent_ring::<[u8; 4]>(&path.0, 4, SyncPolicy::PerWrite)
+                .err()
+                .unwrap()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert!(open_persistent_ring::<u32>(&path.0, 4, SyncPolicy::PerWrite).is_ok());
+    }
+}
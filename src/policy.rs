@@ -0,0 +1,243 @@
+//! Handling of writes to a full ring, see [`FullPolicy`].
+
+use core::ops::Deref;
+
+use crate::atomic::Ordering;
+use crate::error::TryWriteError;
+use crate::{BufferReader, BufferWriter, RD_BUSY};
+
+/// What [`BufferWriter::push`] does if the ring is full, chosen when the ring is created with
+/// [`create_ring_buffer_with_policy`](crate::create_ring_buffer_with_policy).
+///
+/// The policy only applies to [`BufferWriter::push`]. The other ways of writing keep their
+/// own behavior whatever the policy is: [`BufferWriter::try_write`] and the batched writes fail
+/// or stop on a full ring, while [`BufferWriter::write`], `Extend` and `Sink` wait for space.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum FullPolicy {
+    /// Hands the value back in [`TryWriteError::Full`], like [`BufferWriter::try_write`].
+    #[default]
+    Reject,
+
+    /// Drops the new value and returns it as evicted.
+    DropNewest,
+
+    /// Removes the oldest element to make room for the new one and returns it as evicted.
+    ///
+    /// The writer can't take an element the reader is accessing at the same time. If the
+    /// reader is in the middle of reading, or holds a [`PeekRef`](crate::PeekRef),
+    /// [`PeekMut`](crate::PeekMut), [`Iter`](crate::Iter) or [`ReadGrant`](crate::ReadGrant),
+    /// the new value is dropped instead like with [`FullPolicy::DropNewest`].
+    OverwriteOldest,
+
+    /// Blocks until the reader makes room, like [`BufferWriter::write`].
+    #[cfg(feature = "std")]
+    BlockUntilSpace,
+}
+
+impl<T: Sized> BufferWriter<T> {
+    /// Policy applied by [`BufferWriter::push`] to a full ring.
+    pub fn full_policy(&self) -> FullPolicy {
+        self.shared_state.policy
+    }
+
+    /// Number of elements dropped so far because the ring was full.
+    pub fn evicted_count(&self) -> u64 {
+        self.shared_state.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Writes `value`, handling a full ring according to the [`FullPolicy`] of the ring.
+    ///
+    /// Returns the element that was dropped to keep the ring from overflowing, if any. Fails
+    /// with [`TryWriteError::Full`] only for [`FullPolicy::Reject`].
+    pub fn push(&mut self, value: T) -> Result<Option<T>, TryWriteError<T>> {
+        let value = match self.try_write(value) {
+            Ok(()) => return Ok(None),
+            Err(TryWriteError::Full(v)) => v,
+            Err(e) => return Err(e),
+        };
+
+        match self.shared_state.policy {
+            FullPolicy::Reject => Err(TryWriteError::Full(value)),
+            FullPolicy::DropNewest => {
+                self.count_eviction();
+
+                Ok(Some(value))
+            }
+            FullPolicy::OverwriteOldest => self.overwrite_oldest(value),
+            #[cfg(feature = "std")]
+            FullPolicy::BlockUntilSpace => self
+                .write(value)
+                .map(|()| None)
+                .map_err(|e| TryWriteError::Disconnected(e.0)),
+        }
+    }
+
+    fn overwrite_oldest(&mut self, value: T) -> Result<Option<T>, TryWriteError<T>> {
+        let state = self.shared_state.deref();
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+        let mut cur_read_idx = state.rd_index.load(Ordering::Acquire);
+
+        loop {
+            if cur_read_idx & RD_BUSY != 0 {
+                self.count_eviction();
+
+                return Ok(Some(value));
+            }
+
+            // The reader made room in the meantime.
+            if state.free_slots(cur_read_idx, cur_write_idx) > 0 {
+                return self.try_write(value).map(|()| None);
+            }
+
+            match state.rd_index.compare_exchange_weak(
+                cur_read_idx,
+                cur_read_idx + 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => break,
+                Err(v) => cur_read_idx = v,
+            }
+        }
+
+        self.cached_rd_index = cur_read_idx + 1;
+
+        // The slot of the evicted element is the only free one now, unless the storage has more
+        // slots than the capacity.
+        let evicted = unsafe {
+            let evicted = core::ptr::read(state.slot_ptr(cur_read_idx));
+
+            core::ptr::write(state.slot_ptr(cur_write_idx), value);
+
+            evicted
+        };
+
+        state.publish_write(cur_write_idx + 1);
+
+        self.count_eviction();
+
+        Ok(Some(evicted))
+    }
+
+    fn count_eviction(&mut self) {
+        let evicted = &self.shared_state.evicted;
+
+        evicted.store(evicted.load(Ordering::Relaxed) + 1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Number of elements the writer dropped so far because the ring was full.
+    pub fn evicted_count(&self) -> u64 {
+        self.shared_state.evicted.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FullPolicy;
+    use crate::{create_ring_buffer_with_policy, TryWriteError};
+
+    #[test]
+    fn reject_and_drop_newest_test() {
+        let (mut buffer_writer, buffer_reader) =
+            create_ring_buffer_with_policy::<u32>(2, FullPolicy::Reject);
+
+        assert_eq!(buffer_writer.push(1), Ok(None));
+        assert_eq!(buffer_writer.push(2), Ok(None));
+        assert_eq!(buffer_writer.push(3), Err(TryWriteError::Full(3)));
+        assert_eq!(buffer_reader.evicted_count(), 0);
+
+        let (mut buffer_writer, mut buffer_reader) =
+            create_ring_buffer_with_policy::<u32>(2, FullPolicy::DropNewest);
+
+        assert_eq!(buffer_writer.push(1), Ok(None));
+        assert_eq!(buffer_writer.push(2), Ok(None));
+        assert_eq!(buffer_writer.push(3), Ok(Some(3)));
+        assert_eq!(buffer_reader.evicted_count(), 1);
+        assert_eq!(buffer_reader.read_to_vec(4), vec![1, 2]);
+    }
+
+    #[test]
+    fn overwrite_oldest_test() {
+        let (mut buffer_writer, mut buffer_reader) =
+            create_ring_buffer_with_policy::<u32>(3, FullPolicy::OverwriteOldest);
+
+        for idx in 0..3 {
+            assert_eq!(buffer_writer.push(idx), Ok(None));
+        }
+
+        assert_eq!(buffer_writer.push(3), Ok(Some(0)));
+        assert_eq!(buffer_writer.push(4), Ok(Some(1)));
+        assert_eq!(buffer_writer.evicted_count(), 2);
+
+        assert_eq!(buffer_reader.try_read(), Ok(2));
+        assert_eq!(buffer_writer.push(5), Ok(None));
+        assert_eq!(buffer_writer.push(6), Ok(Some(3)));
+
+        // Nothing is evicted while the reader holds a reference to the oldest element.
+        let oldest = buffer_reader.peek().unwrap();
+
+        assert_eq!(*oldest, 4);
+        assert_eq!(buffer_writer.push(7), Ok(Some(7)));
+
+        drop(oldest);
+
+        assert_eq!(buffer_writer.push(8), Ok(Some(4)));
+
+        // Same for the elements handed out by an iterator, even after it is gone.
+        let elements = buffer_reader.iter().collect::<Vec<_>>();
+
+        assert_eq!(buffer_writer.push(9), Ok(Some(9)));
+
+        drop(elements);
+
+        assert_eq!(buffer_writer.push(10), Ok(Some(5)));
+
+        assert_eq!(buffer_reader.read_to_vec(8), vec![6, 8, 10]);
+        assert_eq!(buffer_writer.evicted_count(), 7);
+
+        for idx in 11..15 {
+            assert!(buffer_writer.push(idx).is_ok());
+        }
+
+        assert_eq!(buffer_reader.read_to_vec(8), vec![12, 13, 14]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn overwrite_oldest_threaded_test() {
+        let (mut buffer_writer, mut buffer_reader) =
+            create_ring_buffer_with_policy::<u64>(4, FullPolicy::OverwriteOldest);
+
+        let num_elements = if cfg!(miri) { 500 } else { 100_000 };
+
+        let writer_thread = std::thread::spawn(move || {
+            let mut evicted = 0;
+
+            for idx in 0..num_elements {
+                if buffer_writer.push(idx).unwrap().is_some() {
+                    evicted += 1;
+                }
+            }
+
+            evicted
+        });
+
+        let mut received = 0;
+        let mut last = None;
+
+        for v in buffer_reader.blocking_iter() {
+            assert!(last < Some(v));
+
+            last = Some(v);
+            received += 1;
+        }
+
+        let evicted = writer_thread.join().unwrap();
+
+        assert_eq!(received + evicted, num_elements);
+        assert_eq!(buffer_reader.evicted_count(), evicted);
+    }
+}
@@ -0,0 +1,356 @@
+//! Readiness file descriptors for registering rings with `epoll` based event loops.
+//!
+//! [`BufferReader`] and [`BufferWriter`] each hand out an `eventfd` through [`AsRawFd`] and
+//! [`AsFd`], created on first use. The reader's becomes readable once elements arrive in a ring
+//! it found empty, the writer's once space opens up in a ring it found full, and both once the
+//! ring gets closed. Each starts out readable, so the first wakeup makes the handle check the
+//! ring.
+//!
+//! A handle arms its descriptor when a read or write finds the ring empty or full, at the same
+//! time clearing the descriptor. The other side only writes to it when it is armed, which is
+//! at most once per transition, so a busy ring costs no system calls. Keep reading or writing
+//! after a wakeup until the ring is empty or full, or the descriptor won't fire again.
+//!
+//! With the `mio` feature both handles implement `mio::event::Source`.
+
+use std::io;
+use std::mem::size_of;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use crate::wait::heavy_barrier;
+use crate::{BufferReader, BufferWriter};
+
+/// Lazily created `eventfd` of one side of the ring, signaled through its `WaitSlot`.
+pub(crate) struct Readiness {
+    fd: OnceLock<OwnedFd>,
+
+    // Set by the owning side once it found the ring empty (or full), cleared by the first
+    // `signal` after that.
+    armed: AtomicBool,
+}
+
+impl Readiness {
+    pub(crate) fn new() -> Self {
+        Readiness {
+            fd: OnceLock::new(),
+            armed: AtomicBool::new(false),
+        }
+    }
+
+    fn fd(&self) -> io::Result<BorrowedFd<'_>> {
+        if let Some(fd) = self.fd.get() {
+            return Ok(fd.as_fd());
+        }
+
+        let fd = unsafe { libc::eventfd(1, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Another thread sharing the reader may have been first, its descriptor is kept.
+        let _ = self.fd.set(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        Ok(self.fd.get().unwrap().as_fd())
+    }
+
+    /// Clears the descriptor and arms it. Returns true if the caller has to check the ring
+    /// again, which is the case unless there is no descriptor or it is already armed.
+    pub(crate) fn arm(&self) -> bool {
+        let Some(fd) = self.fd.get() else {
+            return false;
+        };
+
+        if self.armed.load(Ordering::Relaxed) {
+            return false;
+        }
+
+        let mut count = 0u64;
+
+        unsafe {
+            libc::read(
+                fd.as_raw_fd(),
+                &mut count as *mut u64 as *mut libc::c_void,
+                size_of::<u64>(),
+            );
+        }
+
+        self.armed.store(true, Ordering::Relaxed);
+
+        heavy_barrier();
+
+        true
+    }
+
+    /// Drops the arming after the check following [`Readiness::arm`] found the ring ready.
+    pub(crate) fn disarm(&self) {
+        self.armed.store(false, Ordering::Relaxed);
+    }
+
+    /// Makes the descriptor readable if it is armed. Called by the other side from
+    /// `WaitSlot::notify`, after the barrier following the store of its index.
+    pub(crate) fn signal(&self) {
+        if self.armed.load(Ordering::Relaxed) && self.armed.swap(false, Ordering::Relaxed) {
+            if let Some(fd) = self.fd.get() {
+                let count = 1u64;
+
+                unsafe {
+                    libc::write(
+                        fd.as_raw_fd(),
+                        &count as *const u64 as *const libc::c_void,
+                        size_of::<u64>(),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl<T: Sized> BufferReader<T> {
+    /// Descriptor that becomes readable once elements arrive in a ring the reader found empty,
+    /// or once the ring gets closed. Read until the ring is empty after every wakeup, the
+    /// descriptor is only armed again then.
+    pub fn readiness_fd(&self) -> io::Result<BorrowedFd<'_>> {
+        self.shared_state.rd_waiter.readiness.fd()
+    }
+}
+
+impl<T: Sized> BufferWriter<T> {
+    /// Descriptor that becomes readable once space opens up in a ring the writer found full, or
+    /// once the ring gets closed. Write until the ring is full after every wakeup, the
+    /// descriptor is only armed again then.
+    pub fn readiness_fd(&self) -> io::Result<BorrowedFd<'_>> {
+        self.shared_state.wr_waiter.readiness.fd()
+    }
+}
+
+/// Panics if the `eventfd` can't be created, use [`BufferReader::readiness_fd`] to handle that.
+impl<T: Sized> AsFd for BufferReader<T> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.readiness_fd().expect("failed to create eventfd")
+    }
+}
+
+impl<T: Sized> AsRawFd for BufferReader<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+/// Panics if the `eventfd` can't be created, use [`BufferWriter::readiness_fd`] to handle that.
+impl<T: Sized> AsFd for BufferWriter<T> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.readiness_fd().expect("failed to create eventfd")
+    }
+}
+
+impl<T: Sized> AsRawFd for BufferWriter<T> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.as_fd().as_raw_fd()
+    }
+}
+
+#[cfg(feature = "mio")]
+mod mio_source {
+    use std::io;
+    use std::os::fd::AsRawFd;
+
+    use mio::unix::SourceFd;
+    use mio::{event, Interest, Registry, Token};
+
+    use crate::{BufferReader, BufferWriter};
+
+    /// Registers the readiness descriptor, which is only ever readable.
+    impl<T: Sized> event::Source for BufferReader<T> {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).register(registry, token, interests)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).reregister(registry, token, interests)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).deregister(registry)
+        }
+    }
+
+    /// Registers the readiness descriptor, which is only ever readable, so register the writer
+    /// with [`Interest::READABLE`] to learn about free space.
+    impl<T: Sized> event::Source for BufferWriter<T> {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).register(registry, token, interests)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).reregister(registry, token, interests)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+            SourceFd(&self.readiness_fd()?.as_raw_fd()).deregister(registry)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::fd::{AsRawFd, RawFd};
+
+    use crate::{create_ring_buffer, TryReadError, TryWriteError};
+
+    fn is_readable(fd: RawFd) -> bool {
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        assert!(unsafe { libc::poll(&mut pollfd, 1, 0) } >= 0);
+
+        pollfd.revents & libc::POLLIN != 0
+    }
+
+    fn eventfd_count(fd: RawFd) -> u64 {
+        let mut count = 0u64;
+
+        unsafe {
+            libc::read(fd, &mut count as *mut u64 as *mut libc::c_void, 8);
+        }
+
+        count
+    }
+
+    #[test]
+    fn reader_readiness_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(4);
+
+        let fd = buffer_reader.as_raw_fd();
+
+        assert!(is_readable(fd));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Empty));
+        assert!(!is_readable(fd));
+
+        buffer_writer.try_write(1).unwrap();
+
+        assert!(is_readable(fd));
+        assert_eq!(buffer_reader.try_read(), Ok(1));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Empty));
+        assert!(!is_readable(fd));
+
+        // Only the first write after the reader found the ring empty signals.
+        buffer_writer.try_write(2).unwrap();
+        buffer_writer.try_write(3).unwrap();
+        buffer_writer.try_write(4).unwrap();
+
+        assert_eq!(eventfd_count(fd), 1);
+
+        assert_eq!(buffer_reader.try_read(), Ok(2));
+
+        drop(buffer_writer);
+
+        assert_eq!(buffer_reader.by_ref().count(), 2);
+        assert!(!is_readable(fd));
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Disconnected));
+    }
+
+    #[test]
+    fn writer_readiness_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(2);
+
+        let fd = buffer_writer.as_raw_fd();
+
+        buffer_writer.try_write(1).unwrap();
+        buffer_writer.try_write(2).unwrap();
+
+        assert!(is_readable(fd));
+        assert_eq!(buffer_writer.try_write(3), Err(TryWriteError::Full(3)));
+        assert!(!is_readable(fd));
+
+        assert_eq!(buffer_reader.try_read(), Ok(1));
+
+        assert!(is_readable(fd));
+        assert_eq!(buffer_writer.try_write(3), Ok(()));
+        assert_eq!(buffer_writer.try_write(4), Err(TryWriteError::Full(4)));
+        assert!(!is_readable(fd));
+
+        drop(buffer_reader);
+
+        assert!(is_readable(fd));
+    }
+
+    #[cfg(feature = "mio")]
+    #[test]
+    fn mio_readiness_test() {
+        use std::time::Duration;
+
+        use mio::{Events, Interest, Poll, Token};
+
+        let (mut buffer_writer, mut buffer_reader) = create_ring_buffer::<u32>(16);
+
+        let mut poll = Poll::new().unwrap();
+        let mut events = Events::with_capacity(4);
+
+        poll.registry()
+            .register(&mut buffer_reader, Token(1), Interest::READABLE)
+            .unwrap();
+
+        let writer_thread = std::thread::spawn(move || {
+            for idx in 0..100 {
+                while buffer_writer.try_write(idx).is_err() {
+                    std::thread::yield_now();
+                }
+
+                if idx % 10 == 0 {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        });
+
+        let mut expected = 0;
+
+        loop {
+            match buffer_reader.try_read() {
+                Ok(v) => {
+                    assert_eq!(v, expected);
+
+                    expected += 1;
+
+                    continue;
+                }
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => break,
+            }
+
+            poll.poll(&mut events, Some(Duration::from_secs(10)))
+                .unwrap();
+
+            assert!(events.iter().any(|event| event.token() == Token(1)));
+        }
+
+        writer_thread.join().unwrap();
+
+        assert_eq!(expected, 100);
+    }
+}
@@ -0,0 +1,488 @@
+//! Ring of variable length byte records stored in one byte arena.
+//!
+//! Every record starts with a 4 byte header holding its length and is padded to a multiple of 4
+//! bytes, so the next header is aligned again. A record is always contiguous: if it doesn't fit
+//! between the write position and the end of the arena, the writer fills the rest with a
+//! padding record and starts the record at the beginning of the arena. The reader skips padding
+//! records on its own.
+//!
+//! ```
+//! use atomic_ring_buffer::create_record_ring_buffer;
+//!
+//! let (mut buffer_writer, mut buffer_reader) = create_record_ring_buffer(1024);
+//!
+//! buffer_writer.write_record(b"first").unwrap();
+//!
+//! let mut grant = buffer_writer.reserve_record(6).unwrap();
+//!
+//! grant.copy_from_slice(b"second");
+//! grant.commit();
+//!
+//! assert_eq!(&*buffer_reader.read_record().unwrap(), b"first");
+//! assert_eq!(&*buffer_reader.read_record().unwrap(), b"second");
+//! ```
+
+use alloc::sync::Arc;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+use crate::atomic::{AtomicBool, AtomicU64, Ordering};
+use crate::cache_padded::CachePadded;
+use crate::error::{TryReadError, TryReserveError};
+use crate::{alloc_slots, dealloc_slots};
+
+/// Size of the length header in front of every record.
+const HEADER_LEN: u64 = 4;
+
+/// Header of the padding record that fills the arena up to its end.
+const PADDING: u32 = u32::MAX;
+
+/// Indices are byte positions in the arena and free running like the ones of
+/// `SharedBufferState`, records start at positions that are a multiple of [`HEADER_LEN`].
+struct RecordState {
+    // Size of the arena in bytes, a power of two.
+    arena_len: u64,
+
+    wr_index: CachePadded<AtomicU64>,
+    rd_index: CachePadded<AtomicU64>,
+
+    closed: AtomicBool,
+
+    // Zeroed when allocated, as `u32` words so that every header is aligned.
+    arena: NonNull<u32>,
+}
+
+/// Writing half of a record ring, see [`create_record_ring_buffer`].
+pub struct RecordWriter {
+    shared_state: Arc<RecordState>,
+
+    // Last value of `rd_index` seen by the writer, only reloaded once the arena looks full.
+    cached_rd_index: u64,
+}
+
+/// Reading half of a record ring, see [`create_record_ring_buffer`].
+pub struct RecordReader {
+    shared_state: Arc<RecordState>,
+
+    // Last value of `wr_index` seen by the reader, only reloaded once the arena looks empty.
+    cached_wr_index: u64,
+}
+
+/// Space for one record reserved by [`RecordWriter::reserve_record`], dereferences to the
+/// record's bytes.
+///
+/// Dropping the grant without committing discards it, nothing is published.
+pub struct RecordGrant<'a> {
+    writer: &'a mut RecordWriter,
+
+    // Position of the padding record, if any, followed by the position of the record itself.
+    start: u64,
+    record_start: u64,
+    len: usize,
+}
+
+/// Record read by [`RecordReader::read_record`], dereferences to the record's bytes.
+///
+/// Its space is handed back to the writer when the guard is dropped.
+pub struct RecordGuard<'a> {
+    reader: &'a mut RecordReader,
+
+    record_start: u64,
+    len: usize,
+}
+
+impl RecordState {
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+    }
+
+    fn max_record_len(&self) -> usize {
+        max_record_len(self.arena_len)
+    }
+
+    fn offset_of(&self, index: u64) -> u64 {
+        index & (self.arena_len - 1)
+    }
+
+    fn header_ptr(&self, index: u64) -> *mut u32 {
+        unsafe {
+            self.arena
+                .as_ptr()
+                .add((self.offset_of(index) / HEADER_LEN) as usize)
+        }
+    }
+
+    fn data_ptr(&self, record_start: u64) -> *mut u8 {
+        unsafe { self.header_ptr(record_start).add(1) as *mut u8 }
+    }
+}
+
+impl Drop for RecordState {
+    fn drop(&mut self) {
+        unsafe {
+            dealloc_slots(self.arena, (self.arena_len / HEADER_LEN) as usize);
+        }
+    }
+}
+
+// The arena only holds bytes, each record is only accessed by one side at a time.
+unsafe impl Send for RecordState {}
+unsafe impl Sync for RecordState {}
+
+/// Longest record an arena of `arena_len` bytes can hold.
+fn max_record_len(arena_len: u64) -> usize {
+    // Any record up to half of the arena fits behind the padding it may need, whatever the
+    // write position is. The length also has to fit into the header without reading as padding.
+    (arena_len / 2 - HEADER_LEN).min(PADDING as u64 - 1) as usize
+}
+
+/// Space a record of `len` bytes takes up in the arena, including its header.
+fn record_size(len: usize) -> u64 {
+    HEADER_LEN + (len as u64).next_multiple_of(HEADER_LEN)
+}
+
+impl RecordWriter {
+    /// Size of the arena in bytes.
+    pub fn capacity(&self) -> usize {
+        self.shared_state.arena_len as usize
+    }
+
+    /// Longest record that can be written, half of the arena minus the header but at most
+    /// `u32::MAX - 1` bytes.
+    pub fn max_record_len(&self) -> usize {
+        self.shared_state.max_record_len()
+    }
+
+    /// Returns true once the reader has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Copies `record` into the ring as a single record.
+    pub fn write_record(&mut self, record: &[u8]) -> Result<(), TryReserveError> {
+        let mut grant = self.reserve_record(record.len())?;
+
+        grant.copy_from_slice(record);
+        grant.commit();
+
+        Ok(())
+    }
+
+    /// Reserves contiguous space for a record of `len` bytes so it can be written in place.
+    pub fn reserve_record(&mut self, len: usize) -> Result<RecordGrant<'_>, TryReserveError> {
+        let state = self.shared_state.deref();
+
+        if state.is_closed() {
+            return Err(TryReserveError::Disconnected);
+        }
+
+        if len > state.max_record_len() {
+            return Err(TryReserveError::TooLarge);
+        }
+
+        let size = record_size(len);
+
+        let cur_write_idx = state.wr_index.load(Ordering::Relaxed);
+
+        let to_end = state.arena_len - state.offset_of(cur_write_idx);
+
+        // Records never wrap, pad up to the end of the arena instead.
+        let record_start = if size > to_end {
+            cur_write_idx + to_end
+        } else {
+            cur_write_idx
+        };
+
+        let needed = record_start + size - cur_write_idx;
+
+        if state.arena_len - (cur_write_idx - self.cached_rd_index) < needed {
+            self.cached_rd_index = state.rd_index.load(Ordering::Acquire);
+
+            if state.arena_len - (cur_write_idx - self.cached_rd_index) < needed {
+                return Err(TryReserveError::Full);
+            }
+        }
+
+        Ok(RecordGrant {
+            writer: self,
+            start: cur_write_idx,
+            record_start,
+            len,
+        })
+    }
+}
+
+impl Drop for RecordWriter {
+    fn drop(&mut self) {
+        self.shared_state.close();
+    }
+}
+
+impl RecordGrant<'_> {
+    /// Publishes the record to the reader.
+    pub fn commit(self) {
+        let state = self.writer.shared_state.deref();
+
+        unsafe {
+            if self.record_start != self.start {
+                core::ptr::write(state.header_ptr(self.start), PADDING);
+            }
+
+            core::ptr::write(state.header_ptr(self.record_start), self.len as u32);
+        }
+
+        state
+            .wr_index
+            .store(self.record_start + record_size(self.len), Ordering::Release);
+    }
+}
+
+impl Deref for RecordGrant<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let state = self.writer.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts(state.data_ptr(self.record_start), self.len) }
+    }
+}
+
+impl DerefMut for RecordGrant<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let state = self.writer.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts_mut(state.data_ptr(self.record_start), self.len) }
+    }
+}
+
+impl RecordReader {
+    /// Size of the arena in bytes.
+    pub fn capacity(&self) -> usize {
+        self.shared_state.arena_len as usize
+    }
+
+    /// Returns true once the writer has been dropped. There may still be records left to read.
+    pub fn is_closed(&self) -> bool {
+        self.shared_state.is_closed()
+    }
+
+    /// Returns the next record, fails with [`TryReadError::Empty`] if there is none.
+    pub fn read_record(&mut self) -> Result<RecordGuard<'_>, TryReadError> {
+        let state = self.shared_state.deref();
+
+        let mut cur_read_idx = state.rd_index.load(Ordering::Relaxed);
+
+        if cur_read_idx == self.cached_wr_index {
+            self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
+
+            if cur_read_idx == self.cached_wr_index {
+                if !state.is_closed() {
+                    return Err(TryReadError::Empty);
+                }
+
+                // The writer may have published more records right before closing.
+                self.cached_wr_index = state.wr_index.load(Ordering::Acquire);
+
+                if cur_read_idx == self.cached_wr_index {
+                    return Err(TryReadError::Disconnected);
+                }
+            }
+        }
+
+        let mut header = unsafe { core::ptr::read(state.header_ptr(cur_read_idx)) };
+
+        // Padding is published together with the record following it.
+        if header == PADDING {
+            cur_read_idx += state.arena_len - state.offset_of(cur_read_idx);
+
+            header = unsafe { core::ptr::read(state.header_ptr(cur_read_idx)) };
+        }
+
+        Ok(RecordGuard {
+            reader: self,
+            record_start: cur_read_idx,
+            len: header as usize,
+        })
+    }
+}
+
+impl Drop for RecordReader {
+    fn drop(&mut self) {
+        self.shared_state.close();
+    }
+}
+
+impl Deref for RecordGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let state = self.reader.shared_state.deref();
+
+        unsafe { core::slice::from_raw_parts(state.data_ptr(self.record_start), self.len) }
+    }
+}
+
+impl Drop for RecordGuard<'_> {
+    fn drop(&mut self) {
+        self.reader
+            .shared_state
+            .rd_index
+            .store(self.record_start + record_size(self.len), Ordering::Release);
+    }
+}
+
+/// Creates a record ring whose arena holds `buffer_capacity` bytes, rounded up to a power of
+/// two. Records can be up to half of the arena long, see [`RecordWriter::max_record_len`].
+pub fn create_record_ring_buffer(buffer_capacity: usize) -> (RecordWriter, RecordReader) {
+    let arena_len = buffer_capacity
+        .max(4 * HEADER_LEN as usize)
+        .next_power_of_two();
+
+    let words = arena_len / HEADER_LEN as usize;
+
+    let arena = alloc_slots::<u32>(words);
+
+    // Hands out initialized bytes even for records that were reserved but not written yet.
+    unsafe {
+        core::ptr::write_bytes(arena.as_ptr(), 0, words);
+    }
+
+    let shared_state = Arc::new(RecordState {
+        arena_len: arena_len as u64,
+        wr_index: CachePadded(AtomicU64::new(0)),
+        rd_index: CachePadded(AtomicU64::new(0)),
+        closed: AtomicBool::new(false),
+        arena,
+    });
+
+    (
+        RecordWriter {
+            shared_state: shared_state.clone(),
+            cached_rd_index: 0,
+        },
+        RecordReader {
+            shared_state,
+            cached_wr_index: 0,
+        },
+    )
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::{create_record_ring_buffer, max_record_len};
+    use crate::{TryReadError, TryReserveError};
+
+    #[test]
+    fn record_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_record_ring_buffer(64);
+
+        assert_eq!(buffer_writer.max_record_len(), 28);
+        assert_eq!(max_record_len(1 << 40), u32::MAX as usize - 1);
+        assert_eq!(
+            buffer_writer.write_record(&[0; 29]),
+            Err(TryReserveError::TooLarge)
+        );
+
+        assert!(buffer_writer.write_record(b"").is_ok());
+        assert!(buffer_writer.write_record(b"hello").is_ok());
+
+        // Discarded without committing.
+        buffer_writer
+            .reserve_record(3)
+            .unwrap()
+            .copy_from_slice(b"xyz");
+
+        assert!(buffer_writer.write_record(&[7; 28]).is_ok());
+        assert!(buffer_writer.write_record(&[1; 12]).is_ok());
+        assert_eq!(
+            buffer_writer.write_record(b"full"),
+            Err(TryReserveError::Full)
+        );
+
+        assert_eq!(&*buffer_reader.read_record().unwrap(), b"");
+        assert_eq!(&*buffer_reader.read_record().unwrap(), b"hello");
+        assert_eq!(&*buffer_reader.read_record().unwrap(), &[7; 28]);
+        assert_eq!(&*buffer_reader.read_record().unwrap(), &[1; 12]);
+        assert!(matches!(
+            buffer_reader.read_record(),
+            Err(TryReadError::Empty)
+        ));
+
+        drop(buffer_writer);
+
+        assert!(matches!(
+            buffer_reader.read_record(),
+            Err(TryReadError::Disconnected)
+        ));
+    }
+
+    #[test]
+    fn record_wrap_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_record_ring_buffer(32);
+
+        // 12 + 12 bytes, the next record of 8 bytes only fits after wrapping around.
+        assert!(buffer_writer.write_record(b"abcdefgh").is_ok());
+        assert!(buffer_writer.write_record(b"ijklmnop").is_ok());
+        assert_eq!(
+            buffer_writer.write_record(b"qrstuvwx"),
+            Err(TryReserveError::Full)
+        );
+
+        assert_eq!(&*buffer_reader.read_record().unwrap(), b"abcdefgh");
+
+        assert!(buffer_writer.write_record(b"qrstuvwx").is_ok());
+
+        assert_eq!(&*buffer_reader.read_record().unwrap(), b"ijklmnop");
+        assert_eq!(&*buffer_reader.read_record().unwrap(), b"qrstuvwx");
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            buffer_writer.write_record(b"gone"),
+            Err(TryReserveError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn record_threaded_test() {
+        let (mut buffer_writer, mut buffer_reader) = create_record_ring_buffer(256);
+
+        let num_records = if cfg!(miri) { 100 } else { 10_000 };
+
+        let writer_thread = std::thread::spawn(move || {
+            for idx in 0..num_records {
+                let record = vec![idx as u8; idx % 100];
+
+                loop {
+                    match buffer_writer.write_record(&record) {
+                        Ok(()) => break,
+                        Err(TryReserveError::Full) => std::thread::yield_now(),
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            }
+        });
+
+        let mut idx = 0;
+
+        loop {
+            match buffer_reader.read_record() {
+                Ok(record) => {
+                    assert_eq!(&*record, vec![idx as u8; idx % 100]);
+
+                    idx += 1;
+                }
+                Err(TryReadError::Empty) => std::thread::yield_now(),
+                Err(TryReadError::Disconnected) => break,
+            }
+        }
+
+        assert_eq!(idx, num_records);
+
+        writer_thread.join().unwrap();
+    }
+}
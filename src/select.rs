@@ -0,0 +1,354 @@
+//! Waiting on several rings at once, see [`ReaderSet`].
+
+use std::time::{Duration, Instant};
+
+use crate::error::{DisconnectedError, ReadTimeoutError, TryReadError};
+use crate::wait::{heavy_barrier, park_until};
+use crate::BufferReader;
+
+struct Member<T> {
+    key: usize,
+    reader: BufferReader<T>,
+
+    weight: u32,
+    // Picks left before the turn passes to the next member.
+    credit: u32,
+}
+
+impl<T> Member<T> {
+    /// Returns true if a read wouldn't fail with [`TryReadError::Empty`].
+    fn is_ready(&self) -> bool {
+        self.reader.size() > 0 || self.reader.is_closed()
+    }
+}
+
+/// Set of readers of rings created by [`create_ring_buffer`](crate::create_ring_buffer) that
+/// blocks until any of them has elements.
+///
+/// Members are identified by the key returned when inserting them. Ready members are picked
+/// round robin: a member inserted with [`ReaderSet::insert_weighted`] gets up to `weight`
+/// picks in a row before the turn passes on, [`ReaderSet::insert`] uses a weight of 1.
+///
+/// The set parks the current thread on every member while waiting, so don't wait on one of the
+/// members through [`ReaderSet::get_mut`] from another thread at the same time.
+pub struct ReaderSet<T> {
+    members: Vec<Member<T>>,
+
+    // Index of the member whose turn it is.
+    cursor: usize,
+    next_key: usize,
+}
+
+impl<T> Default for ReaderSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ReaderSet<T> {
+    pub fn new() -> Self {
+        ReaderSet {
+            members: Vec::new(),
+            cursor: 0,
+            next_key: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Adds `reader` with a weight of 1 and returns its key.
+    pub fn insert(&mut self, reader: BufferReader<T>) -> usize {
+        self.insert_weighted(reader, 1)
+    }
+
+    /// Adds `reader`, which gets up to `weight` picks in a row, and returns its key. A weight
+    /// of 0 counts as 1.
+    pub fn insert_weighted(&mut self, reader: BufferReader<T>, weight: u32) -> usize {
+        let key = self.next_key;
+
+        self.next_key += 1;
+
+        let weight = weight.max(1);
+
+        self.members.push(Member {
+            key,
+            reader,
+            weight,
+            credit: weight,
+        });
+
+        key
+    }
+
+    /// Removes the member with `key` and returns its reader.
+    pub fn remove(&mut self, key: usize) -> Option<BufferReader<T>> {
+        let idx = self.members.iter().position(|member| member.key == key)?;
+
+        let member = self.members.remove(idx);
+
+        if idx < self.cursor {
+            self.cursor -= 1;
+        }
+
+        if self.cursor >= self.members.len() {
+            self.cursor = 0;
+        }
+
+        Some(member.reader)
+    }
+
+    pub fn get_mut(&mut self, key: usize) -> Option<&mut BufferReader<T>> {
+        self.members
+            .iter_mut()
+            .find(|member| member.key == key)
+            .map(|member| &mut member.reader)
+    }
+
+    /// Returns the key of the next member that has elements or is disconnected, without
+    /// blocking. Returns `None` if there is none.
+    pub fn try_select(&mut self) -> Option<usize> {
+        let count = self.members.len();
+
+        for offset in 0..count {
+            let idx = (self.cursor + offset) % count;
+
+            if !self.members[idx].is_ready() {
+                continue;
+            }
+
+            if idx != self.cursor {
+                let skipped = &mut self.members[self.cursor];
+
+                skipped.credit = skipped.weight;
+
+                self.cursor = idx;
+            }
+
+            let member = &mut self.members[idx];
+
+            member.credit -= 1;
+
+            if member.credit == 0 {
+                member.credit = member.weight;
+
+                self.cursor = (idx + 1) % count;
+            }
+
+            return Some(member.key);
+        }
+
+        None
+    }
+
+    /// Like [`ReaderSet::try_select`] but blocks the current thread until a member is ready.
+    /// Returns `None` right away if the set is empty.
+    pub fn select(&mut self) -> Option<usize> {
+        self.select_until(None)
+    }
+
+    /// Like [`ReaderSet::select`] but gives up after `timeout`.
+    pub fn select_timeout(&mut self, timeout: Duration) -> Option<usize> {
+        self.select_until(Some(Instant::now() + timeout))
+    }
+
+    fn select_until(&mut self, deadline: Option<Instant>) -> Option<usize> {
+        loop {
+            if let Some(key) = self.try_select() {
+                return Some(key);
+            }
+
+            if self.members.is_empty() || deadline.is_some_and(|d| Instant::now() >= d) {
+                return None;
+            }
+
+            for member in &self.members {
+                member.reader.shared_state.rd_waiter.announce_wait();
+            }
+
+            heavy_barrier();
+
+            let selected = self.try_select();
+
+            if selected.is_none() {
+                park_until(deadline);
+            }
+
+            for member in &self.members {
+                member.reader.shared_state.rd_waiter.cancel_wait();
+            }
+
+            if selected.is_some() {
+                return selected;
+            }
+        }
+    }
+
+    /// Reads the next element from the member whose turn it is, blocking the current thread
+    /// until one is available. Returns the element along with the key of its member.
+    ///
+    /// Members found disconnected and empty are removed and dropped. Fails once the set is
+    /// empty.
+    pub fn read(&mut self) -> Result<(usize, T), DisconnectedError> {
+        self.read_until(None).map_err(|_| DisconnectedError)
+    }
+
+    /// Like [`ReaderSet::read`] but gives up after `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<(usize, T), ReadTimeoutError> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
+
+    fn read_until(&mut self, deadline: Option<Instant>) -> Result<(usize, T), ReadTimeoutError> {
+        loop {
+            let Some(key) = self.select_until(deadline) else {
+                return Err(if self.members.is_empty() {
+                    ReadTimeoutError::Disconnected
+                } else {
+                    ReadTimeoutError::Timeout
+                });
+            };
+
+            match self.get_mut(key).unwrap().try_read() {
+                Ok(v) => return Ok((key, v)),
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => {
+                    self.remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::ReaderSet;
+    use crate::{create_ring_buffer, DisconnectedError, ReadTimeoutError};
+
+    #[test]
+    fn fair_and_weighted_test() {
+        let mut reader_set = ReaderSet::new();
+        let mut buffer_writers = Vec::new();
+
+        for weight in [1, 2, 1] {
+            let (mut buffer_writer, buffer_reader) = create_ring_buffer::<u32>(8);
+
+            for idx in 0..4 {
+                buffer_writer.try_write(idx).unwrap();
+            }
+
+            buffer_writers.push(buffer_writer);
+            reader_set.insert_weighted(buffer_reader, weight);
+        }
+
+        let keys = (0..12)
+            .map(|_| reader_set.read().unwrap().0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(keys, vec![0, 1, 1, 2, 0, 1, 1, 2, 0, 2, 0, 2]);
+
+        assert_eq!(
+            reader_set.read_timeout(Duration::from_millis(10)),
+            Err(ReadTimeoutError::Timeout)
+        );
+
+        // Empty members are skipped, so the only ready one is picked every time.
+        buffer_writers[2].try_write(7).unwrap();
+        buffer_writers[2].try_write(8).unwrap();
+
+        assert_eq!(reader_set.read(), Ok((2, 7)));
+        assert_eq!(reader_set.read(), Ok((2, 8)));
+    }
+
+    #[test]
+    fn insert_remove_test() {
+        let mut reader_set = ReaderSet::new();
+
+        assert_eq!(reader_set.select(), None);
+        assert_eq!(reader_set.read(), Err(DisconnectedError));
+
+        let (mut writer_a, reader_a) = create_ring_buffer::<u32>(4);
+        let (mut writer_b, reader_b) = create_ring_buffer::<u32>(4);
+
+        let key_a = reader_set.insert(reader_a);
+        let key_b = reader_set.insert(reader_b);
+
+        assert_eq!(reader_set.try_select(), None);
+
+        writer_b.try_write(1).unwrap();
+
+        assert_eq!(reader_set.select(), Some(key_b));
+
+        let mut reader_b = reader_set.remove(key_b).unwrap();
+
+        assert_eq!(reader_b.try_read(), Ok(1));
+        assert_eq!(reader_set.len(), 1);
+        assert_eq!(reader_set.select_timeout(Duration::from_millis(10)), None);
+
+        writer_a.try_write(2).unwrap();
+
+        let key_b = reader_set.insert(reader_b);
+
+        writer_b.try_write(3).unwrap();
+
+        assert_eq!(reader_set.read(), Ok((key_a, 2)));
+        assert_eq!(reader_set.read(), Ok((key_b, 3)));
+
+        // Disconnected members leave the set once they are drained.
+        drop(writer_a);
+        drop(writer_b);
+
+        assert_eq!(reader_set.read(), Err(DisconnectedError));
+        assert!(reader_set.is_empty());
+    }
+
+    #[test]
+    fn select_threaded_test() {
+        let num_writers = 8;
+        let num_values = if cfg!(miri) { 100 } else { 10_000 };
+
+        let mut reader_set = ReaderSet::new();
+
+        let writer_threads = (0..num_writers)
+            .map(|writer_idx| {
+                let (mut buffer_writer, buffer_reader) = create_ring_buffer::<u64>(16);
+
+                reader_set.insert(buffer_reader);
+
+                std::thread::spawn(move || {
+                    for idx in 0..num_values {
+                        buffer_writer.write(writer_idx * num_values + idx).unwrap();
+
+                        if idx % 1_000 == 0 {
+                            std::thread::sleep(Duration::from_millis(1));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut next = (0..num_writers)
+            .map(|writer_idx| writer_idx * num_values)
+            .collect::<Vec<_>>();
+
+        while let Ok((key, v)) = reader_set.read() {
+            assert_eq!(v, next[key]);
+
+            next[key] += 1;
+        }
+
+        for writer_thread in writer_threads {
+            writer_thread.join().unwrap();
+        }
+
+        for (writer_idx, next) in next.into_iter().enumerate() {
+            assert_eq!(next, (writer_idx as u64 + 1) * num_values);
+        }
+    }
+}
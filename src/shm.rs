@@ -0,0 +1,970 @@
+//! Ring buffer in shared memory for passing elements between processes on Linux.
+//!
+//! The ring lives in a mapping of a POSIX shared memory object, created by [`create_shm`] and
+//! opened by name with [`open_shm`], or of an anonymous `memfd` from [`create_shm_memfd`] whose
+//! file descriptor is passed to the other process over a [`UnixStream`] with
+//! [`ShmWriter::send_fd`] and [`receive_shm`]. The process creating the ring writes into it,
+//! the one attaching reads from it.
+//!
+//! The mapping starts with a `repr(C)` header describing the ring, which is checked when
+//! attaching, followed by the same free running indices the heap allocated rings use and the
+//! slots. Elements are copied in and out bytewise, so `T` must implement [`Pod`]. A ring has a
+//! single reader, attaching a second one fails while the first is still around.
+//!
+//! The ring is closed when either handle is dropped. A process that dies without dropping its
+//! handle leaves the ring open.
+//!
+//! The blocking [`ShmReader::read`] and [`ShmWriter::write`] sleep on a futex next to the index
+//! of the other side, which works across processes. The other side only issues a
+//! `FUTEX_WAKE` if it finds the waiting flag next to its index set. A process dying while the
+//! other one sleeps doesn't wake it, use the variants with a timeout if that must be handled.
+
+use std::ffi::CString;
+use std::io;
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+// Other processes can't take part in the fallback of `portable-atomic`, always use native
+// atomics in shared memory.
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+
+use crate::cache_padded::CachePadded;
+use crate::error::{
+    DisconnectedError, ReadTimeoutError, TryReadError, TryWriteError, WriteError, WriteTimeoutError,
+};
+use crate::mmap::{cvt, invalid_data, Mapping, Pod};
+
+/// Identifies a mapping holding a ring, stored last when it is created.
+const SHM_MAGIC: u64 = u64::from_le_bytes(*b"ATRINGSM");
+
+/// Version of the layout of [`ShmState`] and the slots following it.
+const SHM_LAYOUT_VERSION: u32 = 3;
+
+#[repr(C)]
+struct ShmHeader {
+    magic: AtomicU64,
+    version: u32,
+    element_size: u32,
+    element_align: u32,
+    // Non-zero while a reader is attached.
+    reader: AtomicU32,
+    capacity: u64,
+}
+
+/// Free running index of one side together with the futex the other side sleeps on.
+///
+/// A sleeper sets `waiters` and re-checks the ring before waiting on `event`. The owning side
+/// stores `index` and then bumps `event` and wakes the sleeper if it finds `waiters` set; the
+/// `SeqCst` fences on both sides make sure that either the sleeper sees the new index or the
+/// owner sees the flag.
+#[repr(C)]
+struct ShmIndex {
+    index: AtomicU64,
+    event: AtomicU32,
+    waiters: AtomicU32,
+}
+
+impl ShmIndex {
+    /// Announces a sleeper, returns the event to pass to [`ShmIndex::wait`] once the ring has
+    /// been checked again.
+    fn prepare_wait(&self) -> u32 {
+        self.waiters.store(1, Ordering::Relaxed);
+
+        fence(Ordering::SeqCst);
+
+        self.event.load(Ordering::Acquire)
+    }
+
+    fn cancel_wait(&self) {
+        self.waiters.store(0, Ordering::Relaxed);
+    }
+
+    /// Sleeps until `event` changes or `deadline` has passed. Spurious wakeups are possible,
+    /// callers are expected to loop.
+    fn wait(&self, event: u32, deadline: Option<Instant>) {
+        let timeout = match deadline {
+            Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                Some(timeout) => Some(timeout),
+                None => {
+                    self.cancel_wait();
+
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        futex_wait(&self.event, event, timeout);
+
+        self.cancel_wait();
+    }
+
+    /// Wakes the sleeper, if any, after `index` has been stored.
+    fn wake(&self) {
+        fence(Ordering::SeqCst);
+
+        if self.waiters.load(Ordering::Relaxed) != 0 {
+            self.event.fetch_add(1, Ordering::Release);
+
+            futex_wake(&self.event);
+        }
+    }
+}
+
+fn futex_wait(word: &AtomicU32, expected: u32, timeout: Option<Duration>) {
+    let timespec = timeout.map(|timeout| libc::timespec {
+        tv_sec: timeout.as_secs().min(libc::time_t::MAX as u64) as libc::time_t,
+        tv_nsec: timeout.subsec_nanos() as libc::c_long,
+    });
+
+    // Not `FUTEX_PRIVATE_FLAG`, the sleeper and the waker may be in different processes.
+    // Interruptions, timeouts and a changed word are all left to the caller's loop.
+    unsafe {
+        libc::syscall(
+            libc::SYS_futex,
+            word.as_ptr(),
+            libc::FUTEX_WAIT,
+            expected,
+            timespec
+                .as_ref()
+                .map_or(std::ptr::null(), |t| t as *const libc::timespec),
+        );
+    }
+}
+
+fn futex_wake(word: &AtomicU32) {
+    unsafe {
+        libc::syscall(libc::SYS_futex, word.as_ptr(), libc::FUTEX_WAKE, 1);
+    }
+}
+
+/// Start of the mapping, the slots follow at [`slots_offset`].
+#[repr(C)]
+struct ShmState {
+    header: ShmHeader,
+
+    wr_index: CachePadded<ShmIndex>,
+    rd_index: CachePadded<ShmIndex>,
+
+    closed: AtomicU32,
+}
+
+/// Offset of the first slot from the start of the mapping.
+fn slots_offset<T>() -> usize {
+    size_of::<ShmState>().next_multiple_of(align_of::<T>())
+}
+
+fn mapping_len<T>(capacity: usize) -> io::Result<usize> {
+    capacity
+        .checked_mul(size_of::<T>())
+        .and_then(|len| len.checked_add(slots_offset::<T>()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "ring buffer capacity overflow"))
+}
+
+/// Mapping of the whole ring.
+struct ShmMapping<T> {
+    map: Mapping,
+    fd: OwnedFd,
+
+    slot_count: u64,
+    slot_mask: u64,
+    masked: bool,
+
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> ShmMapping<T> {
+    fn map(fd: OwnedFd, len: usize) -> io::Result<Self> {
+        Ok(ShmMapping {
+            map: Mapping::new(fd.as_fd(), len)?,
+            fd,
+            slot_count: 0,
+            slot_mask: 0,
+            masked: false,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Sizes the empty file behind `fd` for a ring of `capacity` elements and initializes it.
+    fn create(fd: OwnedFd, capacity: usize) -> io::Result<Self> {
+        let capacity = capacity.max(1);
+        let len = mapping_len::<T>(capacity)?;
+
+        cvt(unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) })?;
+
+        let mut mapping = Self::map(fd, len)?;
+
+        // The file is zero filled, so the indices and the closed flag start out as zero.
+        unsafe {
+            let header = &mut (*(mapping.map.as_ptr() as *mut ShmState)).header;
+
+            header.version = SHM_LAYOUT_VERSION;
+            header.element_size = size_of::<T>() as u32;
+            header.element_align = align_of::<T>() as u32;
+            header.capacity = capacity as u64;
+        }
+
+        mapping
+            .state()
+            .header
+            .magic
+            .store(SHM_MAGIC, Ordering::Release);
+        mapping.set_capacity(capacity as u64);
+
+        Ok(mapping)
+    }
+
+    /// Maps the ring behind `fd` after checking that its header matches `T`.
+    fn attach(fd: OwnedFd) -> io::Result<Self> {
+        let mut stat = unsafe { std::mem::zeroed::<libc::stat>() };
+
+        cvt(unsafe { libc::fstat(fd.as_raw_fd(), &mut stat) })?;
+
+        let file_len = stat.st_size as usize;
+
+        if file_len < size_of::<ShmState>() {
+            return Err(invalid_data(
+                "shared memory object is too small for a ring buffer",
+            ));
+        }
+
+        let mut mapping = Self::map(fd, file_len)?;
+
+        let header = &mapping.state().header;
+
+        if header.magic.load(Ordering::Acquire) != SHM_MAGIC {
+            return Err(invalid_data(
+                "shared memory object doesn't hold a ring buffer",
+            ));
+        }
+
+        if header.version != SHM_LAYOUT_VERSION {
+            return Err(invalid_data("unsupported ring buffer layout version"));
+        }
+
+        if header.element_size as usize != size_of::<T>()
+            || header.element_align as usize != align_of::<T>()
+        {
+            return Err(invalid_data(
+                "ring buffer holds elements of a different type",
+            ));
+        }
+
+        let capacity = header.capacity;
+
+        if capacity == 0 || mapping_len::<T>(capacity as usize).ok() != Some(file_len) {
+            return Err(invalid_data("ring buffer capacity doesn't match its size"));
+        }
+
+        mapping.set_capacity(capacity);
+
+        Ok(mapping)
+    }
+
+    fn set_capacity(&mut self, capacity: u64) {
+        self.slot_count = capacity;
+        self.slot_mask = capacity.wrapping_sub(1);
+        self.masked = capacity.is_power_of_two();
+    }
+
+    fn state(&self) -> &ShmState {
+        unsafe { &*(self.map.as_ptr() as *const ShmState) }
+    }
+
+    fn slot_ptr(&self, index: u64) -> *mut T {
+        let slot = if self.masked {
+            index & self.slot_mask
+        } else {
+            index % self.slot_count
+        };
+
+        unsafe {
+            self.map
+                .as_ptr()
+                .add(slots_offset::<T>())
+                .cast::<T>()
+                .add(slot as usize)
+        }
+    }
+
+    fn used_slots(cur_read_idx: u64, cur_write_idx: u64) -> usize {
+        cur_write_idx.wrapping_sub(cur_read_idx) as usize
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state().closed.load(Ordering::Acquire) != 0
+    }
+
+    fn close(&self) {
+        let state = self.state();
+
+        state.closed.store(1, Ordering::Release);
+
+        state.wr_index.wake();
+        state.rd_index.wake();
+    }
+}
+
+/// Writing half of a ring in shared memory, see [`create_shm`].
+pub struct ShmWriter<T: Pod> {
+    mapping: ShmMapping<T>,
+
+    // Removed from `/dev/shm` once the writer is dropped.
+    name: Option<CString>,
+
+    // Last value of `rd_index` seen by the writer, only reloaded once the ring looks full.
+    cached_rd_index: u64,
+}
+
+/// Reading half of a ring in shared memory, see [`open_shm`].
+pub struct ShmReader<T: Pod> {
+    mapping: ShmMapping<T>,
+
+    // Last value of `wr_index` seen by the reader, only reloaded once the ring looks empty.
+    cached_wr_index: u64,
+}
+
+// The mapping is only accessed through the indices of the ring, like the heap allocated rings.
+unsafe impl<T: Pod + Send> Send for ShmWriter<T> {}
+unsafe impl<T: Pod + Send> Send for ShmReader<T> {}
+
+impl<T: Pod> ShmWriter<T> {
+    pub fn size(&self) -> usize {
+        let state = self.mapping.state();
+
+        ShmMapping::<T>::used_slots(
+            state.rd_index.index.load(Ordering::Acquire),
+            state.wr_index.index.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mapping.slot_count as usize
+    }
+
+    /// Returns true once either side closed the ring or the reader has been dropped.
+    pub fn is_closed(&self) -> bool {
+        self.mapping.is_closed()
+    }
+
+    /// Closes the ring. Elements already written can still be read, further writes fail.
+    pub fn close(&mut self) {
+        self.mapping.close();
+    }
+
+    pub fn try_write(&mut self, value: T) -> Result<(), TryWriteError<T>> {
+        if self.mapping.is_closed() {
+            return Err(TryWriteError::Disconnected(value));
+        }
+
+        let state = self.mapping.state();
+
+        let cur_write_idx = state.wr_index.index.load(Ordering::Relaxed);
+
+        if ShmMapping::<T>::used_slots(self.cached_rd_index, cur_write_idx) == self.capacity() {
+            self.cached_rd_index = state.rd_index.index.load(Ordering::Acquire);
+
+            if ShmMapping::<T>::used_slots(self.cached_rd_index, cur_write_idx) == self.capacity() {
+                return Err(TryWriteError::Full(value));
+            }
+        }
+
+        unsafe {
+            core::ptr::write(self.mapping.slot_ptr(cur_write_idx), value);
+        }
+
+        state
+            .wr_index
+            .index
+            .store(cur_write_idx + 1, Ordering::Release);
+        state.wr_index.wake();
+
+        Ok(())
+    }
+
+    /// Writes `value`, sleeping until the reader makes room for it. Fails and returns the value
+    /// back if the ring gets closed.
+    pub fn write(&mut self, value: T) -> Result<(), WriteError<T>> {
+        self.write_until(value, None)
+            .map_err(|e| WriteError(e.into_inner()))
+    }
+
+    /// Like [`ShmWriter::write`] but gives up after `timeout` and returns the value back.
+    pub fn write_timeout(
+        &mut self,
+        value: T,
+        timeout: Duration,
+    ) -> Result<(), WriteTimeoutError<T>> {
+        self.write_until(value, Some(Instant::now() + timeout))
+    }
+
+    fn write_until(
+        &mut self,
+        mut value: T,
+        deadline: Option<Instant>,
+    ) -> Result<(), WriteTimeoutError<T>> {
+        loop {
+            value = match self.try_write(value) {
+                Ok(()) => return Ok(()),
+                Err(TryWriteError::Full(v)) => v,
+                Err(TryWriteError::Disconnected(v)) => {
+                    return Err(WriteTimeoutError::Disconnected(v))
+                }
+            };
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(WriteTimeoutError::Timeout(value));
+            }
+
+            let event = self.mapping.state().rd_index.prepare_wait();
+
+            value = match self.try_write(value) {
+                Ok(()) => {
+                    self.mapping.state().rd_index.cancel_wait();
+
+                    return Ok(());
+                }
+                Err(TryWriteError::Full(v)) => v,
+                Err(TryWriteError::Disconnected(v)) => {
+                    self.mapping.state().rd_index.cancel_wait();
+
+                    return Err(WriteTimeoutError::Disconnected(v));
+                }
+            };
+
+            self.mapping.state().rd_index.wait(event, deadline);
+        }
+    }
+
+    /// Sends the file descriptor of the ring over `stream`, the other process attaches to it
+    /// with [`receive_shm`].
+    pub fn send_fd(&self, stream: &UnixStream) -> io::Result<()> {
+        send_fd(stream, self.mapping.fd.as_raw_fd())
+    }
+}
+
+impl<T: Pod> Drop for ShmWriter<T> {
+    fn drop(&mut self) {
+        self.mapping.close();
+
+        if let Some(name) = &self.name {
+            unsafe {
+                libc::shm_unlink(name.as_ptr());
+            }
+        }
+    }
+}
+
+impl<T: Pod> ShmReader<T> {
+    pub fn size(&self) -> usize {
+        let state = self.mapping.state();
+
+        ShmMapping::<T>::used_slots(
+            state.rd_index.index.load(Ordering::Relaxed),
+            state.wr_index.index.load(Ordering::Acquire),
+        )
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mapping.slot_count as usize
+    }
+
+    /// Returns true once either side closed the ring or the writer has been dropped.
+    /// There may still be elements left to read.
+    pub fn is_closed(&self) -> bool {
+        self.mapping.is_closed()
+    }
+
+    /// Closes the ring. The writer can't write any more elements but the ones already written
+    /// can still be read.
+    pub fn close(&mut self) {
+        self.mapping.close();
+    }
+
+    pub fn try_read(&mut self) -> Result<T, TryReadError> {
+        let state = self.mapping.state();
+
+        let cur_read_idx = state.rd_index.index.load(Ordering::Relaxed);
+
+        if cur_read_idx == self.cached_wr_index {
+            self.cached_wr_index = state.wr_index.index.load(Ordering::Acquire);
+
+            if cur_read_idx == self.cached_wr_index {
+                if !self.mapping.is_closed() {
+                    return Err(TryReadError::Empty);
+                }
+
+                // The writer may have published more elements right before closing.
+                self.cached_wr_index = state.wr_index.index.load(Ordering::Acquire);
+
+                if cur_read_idx == self.cached_wr_index {
+                    return Err(TryReadError::Disconnected);
+                }
+            }
+        }
+
+        let ret = unsafe { core::ptr::read(self.mapping.slot_ptr(cur_read_idx)) };
+
+        state
+            .rd_index
+            .index
+            .store(cur_read_idx + 1, Ordering::Release);
+        state.rd_index.wake();
+
+        Ok(ret)
+    }
+
+    /// Reads the next element, sleeping until the writer publishes one. Fails once the ring is
+    /// empty and closed.
+    pub fn read(&mut self) -> Result<T, DisconnectedError> {
+        self.read_until(None).map_err(|_| DisconnectedError)
+    }
+
+    /// Like [`ShmReader::read`] but gives up after `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<T, ReadTimeoutError> {
+        self.read_until(Some(Instant::now() + timeout))
+    }
+
+    fn read_until(&mut self, deadline: Option<Instant>) -> Result<T, ReadTimeoutError> {
+        loop {
+            match self.try_read() {
+                Ok(v) => return Ok(v),
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => return Err(ReadTimeoutError::Disconnected),
+            }
+
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Err(ReadTimeoutError::Timeout);
+            }
+
+            let event = self.mapping.state().wr_index.prepare_wait();
+
+            match self.try_read() {
+                Ok(v) => {
+                    self.mapping.state().wr_index.cancel_wait();
+
+                    return Ok(v);
+                }
+                Err(TryReadError::Empty) => {}
+                Err(TryReadError::Disconnected) => {
+                    self.mapping.state().wr_index.cancel_wait();
+
+                    return Err(ReadTimeoutError::Disconnected);
+                }
+            }
+
+            self.mapping.state().wr_index.wait(event, deadline);
+        }
+    }
+}
+
+/// Yields elements until the ring is empty, see [`ShmReader::try_read`].
+impl<T: Pod> Iterator for ShmReader<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.try_read().ok()
+    }
+}
+
+impl<T: Pod> Drop for ShmReader<T> {
+    fn drop(&mut self) {
+        self.mapping.close();
+
+        self.mapping
+            .state()
+            .header
+            .reader
+            .store(0, Ordering::Release);
+    }
+}
+
+fn shm_name(name: &str) -> io::Result<CString> {
+    CString::new(name).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "nul in name"))
+}
+
+/// Creates the shared memory object `name` (like `/my-ring`, see `shm_open(3)`) holding a ring
+/// of up to `buffer_capacity` elements and returns its writer. Fails if the object exists.
+///
+/// The object is removed again when the writer is dropped, processes that opened it before keep
+/// their mapping.
+pub fn create_shm<T: Pod>(name: &str, buffer_capacity: usize) -> io::Result<ShmWriter<T>> {
+    let name = shm_name(name)?;
+
+    let fd = cvt(unsafe {
+        libc::shm_open(
+            name.as_ptr(),
+            libc::O_RDWR | libc::O_CREAT | libc::O_EXCL | libc::O_CLOEXEC,
+            0o600,
+        )
+    })?;
+
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mapping = match ShmMapping::create(fd, buffer_capacity) {
+        Ok(mapping) => mapping,
+        Err(e) => {
+            unsafe {
+                libc::shm_unlink(name.as_ptr());
+            }
+
+            return Err(e);
+        }
+    };
+
+    Ok(ShmWriter {
+        mapping,
+        name: Some(name),
+        cached_rd_index: 0,
+    })
+}
+
+/// Opens the ring created by [`create_shm`] under `name` and returns its reader. Fails with
+/// [`io::ErrorKind::InvalidData`] if the object doesn't hold a ring of `T` and with
+/// [`io::ErrorKind::ResourceBusy`] if another reader is attached to it.
+pub fn open_shm<T: Pod>(name: &str) -> io::Result<ShmReader<T>> {
+    let name = shm_name(name)?;
+
+    let fd = cvt(unsafe { libc::shm_open(name.as_ptr(), libc::O_RDWR | libc::O_CLOEXEC, 0) })?;
+
+    shm_reader(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Creates a ring of up to `buffer_capacity` elements in an anonymous `memfd` and returns its
+/// writer. Pass the ring to another process with [`ShmWriter::send_fd`].
+pub fn create_shm_memfd<T: Pod>(buffer_capacity: usize) -> io::Result<ShmWriter<T>> {
+    let fd = cvt(unsafe { libc::memfd_create(c"atomic_ring_buffer".as_ptr(), libc::MFD_CLOEXEC) })?;
+
+    Ok(ShmWriter {
+        mapping: ShmMapping::create(unsafe { OwnedFd::from_raw_fd(fd) }, buffer_capacity)?,
+        name: None,
+        cached_rd_index: 0,
+    })
+}
+
+/// Receives a ring sent with [`ShmWriter::send_fd`] and returns its reader. Fails like
+/// [`open_shm`].
+pub fn receive_shm<T: Pod>(stream: &UnixStream) -> io::Result<ShmReader<T>> {
+    shm_reader(receive_fd(stream)?)
+}
+
+fn shm_reader<T: Pod>(fd: OwnedFd) -> io::Result<ShmReader<T>> {
+    let mapping = ShmMapping::attach(fd)?;
+
+    if mapping
+        .state()
+        .header
+        .reader
+        .compare_exchange(0, 1, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::ResourceBusy,
+            "ring buffer already has a reader",
+        ));
+    }
+
+    let cached_wr_index = mapping.state().wr_index.index.load(Ordering::Acquire);
+
+    Ok(ShmReader {
+        mapping,
+        cached_wr_index,
+    })
+}
+
+/// Room for the control message carrying a single file descriptor, aligned for `cmsghdr`.
+#[repr(C, align(8))]
+struct FdControl([u8; 64]);
+
+fn send_fd(stream: &UnixStream, fd: RawFd) -> io::Result<()> {
+    let mut payload = [0u8; 1];
+    let mut control = FdControl([0; 64]);
+
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+
+    unsafe {
+        let mut msg = std::mem::zeroed::<libc::msghdr>();
+
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = libc::CMSG_SPACE(size_of::<RawFd>() as u32) as _;
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(size_of::<RawFd>() as u32) as _;
+
+        core::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+        if libc::sendmsg(stream.as_raw_fd(), &msg, libc::MSG_NOSIGNAL) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+fn receive_fd(stream: &UnixStream) -> io::Result<OwnedFd> {
+    let mut payload = [0u8; 1];
+    let mut control = FdControl([0; 64]);
+
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+
+    unsafe {
+        let mut msg = std::mem::zeroed::<libc::msghdr>();
+
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control.0.len() as _;
+
+        if libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            return Err(invalid_data("no file descriptor received"));
+        }
+
+        let fd = core::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+
+        Ok(OwnedFd::from_raw_fd(fd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use std::time::Duration;
+
+    use super::{create_shm, create_shm_memfd, open_shm, receive_shm, ShmReader};
+    use crate::{ReadTimeoutError, TryReadError, TryWriteError, WriteTimeoutError};
+
+    fn unique_name() -> String {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        format!(
+            "/atomic_ring_buffer_test_{}_{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Runs `f` in a forked child process and returns whether it succeeded.
+    fn in_child_process<F: FnOnce() -> bool>(f: F) -> bool {
+        match unsafe { libc::fork() } {
+            -1 => panic!("{}", io::Error::last_os_error()),
+            0 => {
+                let ok = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or(false);
+
+                unsafe { libc::_exit(if ok { 0 } else { 1 }) }
+            }
+            pid => {
+                let mut status = 0;
+
+                assert_eq!(unsafe { libc::waitpid(pid, &mut status, 0) }, pid);
+
+                libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0
+            }
+        }
+    }
+
+    /// Reads `0..count` from the ring, yielding until the writer is done.
+    fn read_sequence(mut buffer_reader: ShmReader<u64>, count: u64) -> bool {
+        let mut expected = 0;
+
+        loop {
+            match buffer_reader.try_read() {
+                Ok(v) if v == expected => expected += 1,
+                Ok(_) => return false,
+                Err(TryReadError::Empty) => std::thread::yield_now(),
+                Err(TryReadError::Disconnected) => return expected == count,
+            }
+        }
+    }
+
+    #[test]
+    fn shm_header_test() {
+        let name = unique_name();
+
+        let mut buffer_writer = create_shm::<u32>(&name, 4).unwrap();
+
+        assert_eq!(
+            create_shm::<u32>(&name, 4).err().unwrap().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            open_shm::<u64>(&name).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut buffer_reader = open_shm::<u32>(&name).unwrap();
+
+        assert_eq!(buffer_reader.capacity(), 4);
+        assert_eq!(
+            open_shm::<u32>(&name).err().unwrap().kind(),
+            io::ErrorKind::ResourceBusy
+        );
+
+        for idx in 0..4 {
+            assert!(buffer_writer.try_write(idx).is_ok());
+        }
+
+        assert_eq!(buffer_writer.try_write(4), Err(TryWriteError::Full(4)));
+        assert_eq!(buffer_reader.by_ref().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+
+        drop(buffer_writer);
+
+        assert_eq!(buffer_reader.try_read(), Err(TryReadError::Disconnected));
+        assert_eq!(
+            open_shm::<u32>(&name).err().unwrap().kind(),
+            io::ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn shm_fork_test() {
+        let name = unique_name();
+        let count = 100_000;
+
+        let mut buffer_writer = create_shm::<u64>(&name, 64).unwrap();
+
+        std::thread::scope(|s| {
+            let child =
+                s.spawn(|| in_child_process(|| read_sequence(open_shm(&name).unwrap(), count)));
+
+            for idx in 0..count {
+                while buffer_writer.try_write(idx).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+
+            buffer_writer.close();
+
+            assert!(child.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn shm_memfd_test() {
+        let count = 10_000;
+
+        let (parent_stream, child_stream) = UnixStream::pair().unwrap();
+
+        let mut buffer_writer = create_shm_memfd::<u64>(16).unwrap();
+
+        buffer_writer.send_fd(&parent_stream).unwrap();
+
+        drop(parent_stream);
+
+        std::thread::scope(|s| {
+            let child = s.spawn(|| {
+                in_child_process(|| read_sequence(receive_shm(&child_stream).unwrap(), count))
+            });
+
+            for idx in 0..count {
+                while buffer_writer.try_write(idx).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+
+            buffer_writer.close();
+
+            assert!(child.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn shm_blocking_fork_test() {
+        let name = unique_name();
+        let count = 10_000;
+
+        let mut buffer_writer = create_shm::<u64>(&name, 4).unwrap();
+
+        std::thread::scope(|s| {
+            let child = s.spawn(|| {
+                in_child_process(|| {
+                    let mut buffer_reader = open_shm::<u64>(&name).unwrap();
+
+                    // Lets the writer sleep on the full ring.
+                    std::thread::sleep(Duration::from_millis(50));
+
+                    let mut expected = 0;
+
+                    while let Ok(v) = buffer_reader.read() {
+                        if v != expected {
+                            return false;
+                        }
+
+                        expected += 1;
+                    }
+
+                    expected == count
+                })
+            });
+
+            for idx in 0..count {
+                // Lets the reader sleep on the empty ring now and then.
+                if idx % 1_000 == 999 {
+                    std::thread::sleep(Duration::from_millis(5));
+                }
+
+                buffer_writer.write(idx).unwrap();
+            }
+
+            buffer_writer.close();
+
+            assert!(child.join().unwrap());
+        });
+    }
+
+    #[test]
+    fn shm_timeout_test() {
+        let name = unique_name();
+
+        let mut buffer_writer = create_shm::<u32>(&name, 2).unwrap();
+        let mut buffer_reader = open_shm::<u32>(&name).unwrap();
+
+        let timeout = Duration::from_millis(10);
+
+        assert_eq!(
+            buffer_reader.read_timeout(timeout),
+            Err(ReadTimeoutError::Timeout)
+        );
+
+        buffer_writer.write(0).unwrap();
+        buffer_writer.write(1).unwrap();
+
+        assert_eq!(
+            buffer_writer.write_timeout(2, timeout),
+            Err(WriteTimeoutError::Timeout(2))
+        );
+        assert_eq!(buffer_reader.read_timeout(timeout), Ok(0));
+        assert_eq!(buffer_writer.write_timeout(2, timeout), Ok(()));
+
+        drop(buffer_reader);
+
+        assert_eq!(
+            buffer_writer.write_timeout(3, timeout),
+            Err(WriteTimeoutError::Disconnected(3))
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Slot storage of the rings with several writers, shared by the rings of
+//! [`create_mpsc_ring_buffer`](crate::create_mpsc_ring_buffer) and [`MpmcQueue`](crate::MpmcQueue).
+//!
+//! Writers claim a position by advancing `wr_index` with a compare-exchange and only write the
+//! slot afterwards, so `wr_index` alone can't tell the reader which slots are published. Every
+//! slot carries a stamp instead: a slot stamped `2 * p` is free for the element at position `p`,
+//! a slot stamped `2 * p + 1` holds it. The reader frees a slot by stamping it for the position
+//! it will be written at next, `p + slot_count`. Doubling the positions keeps the two states
+//! apart even if there is only a single slot.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+// Loom only replaces the atomics of these rings, the others need `const` constructors.
+#[cfg(not(loom))]
+use crate::atomic::{AtomicU64, Ordering};
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cache_padded::CachePadded;
+use crate::{alloc_slots, dealloc_slots};
+
+struct Slot<T> {
+    stamp: AtomicU64,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Stamped slots along with the indices of the positions claimed so far. Drops the elements
+/// still stored when it is dropped.
+pub(crate) struct StampedSlots<T: Sized> {
+    slot_count: u64,
+    slot_mask: u64,
+    masked: bool,
+
+    wr_index: CachePadded<AtomicU64>,
+    rd_index: CachePadded<AtomicU64>,
+
+    // Allocated like the storage of `create_ring_buffer`, with exactly `slot_count` slots.
+    slots: NonNull<Slot<T>>,
+}
+
+impl<T: Sized> StampedSlots<T> {
+    pub(crate) fn new(buffer_capacity: usize) -> Self {
+        let slot_count = buffer_capacity.max(1);
+
+        let slots = alloc_slots::<Slot<T>>(slot_count);
+
+        for idx in 0..slot_count {
+            unsafe {
+                core::ptr::write(
+                    slots.as_ptr().add(idx),
+                    Slot {
+                        stamp: AtomicU64::new(2 * idx as u64),
+                        value: UnsafeCell::new(MaybeUninit::uninit()),
+                    },
+                );
+            }
+        }
+
+        StampedSlots {
+            slot_count: slot_count as u64,
+            slot_mask: (slot_count as u64).wrapping_sub(1),
+            masked: slot_count.is_power_of_two(),
+            wr_index: CachePadded(AtomicU64::new(0)),
+            rd_index: CachePadded(AtomicU64::new(0)),
+            slots,
+        }
+    }
+
+    /// Number of claimed slots, including the ones still being written or read.
+    pub(crate) fn size(&self) -> usize {
+        let cur_read_idx = self.rd_index.load(Ordering::Acquire);
+        let cur_write_idx = self.wr_index.load(Ordering::Acquire);
+
+        cur_write_idx.wrapping_sub(cur_read_idx) as usize
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.slot_count as usize
+    }
+
+    fn slot(&self, index: u64) -> &Slot<T> {
+        let slot = if self.masked {
+            index & self.slot_mask
+        } else {
+            index % self.slot_count
+        };
+
+        unsafe { &*self.slots.as_ptr().add(slot as usize) }
+    }
+
+    /// Claims the next free position and writes `value` to it, hands the value back if all
+    /// slots are taken.
+    pub(crate) fn push(&self, value: T) -> Result<(), T> {
+        let mut cur_write_idx = self.wr_index.load(Ordering::Relaxed);
+
+        loop {
+            let slot = self.slot(cur_write_idx);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp == 2 * cur_write_idx {
+                match self.wr_index.compare_exchange_weak(
+                    cur_write_idx,
+                    cur_write_idx + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        unsafe {
+                            (*slot.value.get()).write(value);
+                        }
+
+                        slot.stamp.store(2 * cur_write_idx + 1, Ordering::Release);
+
+                        return Ok(());
+                    }
+                    Err(v) => cur_write_idx = v,
+                }
+            } else if (stamp.wrapping_sub(2 * cur_write_idx) as i64) < 0 {
+                // The slot still holds the element written one lap earlier. The ring is full
+                // unless another writer moved on in the meantime.
+                let latest_write_idx = self.wr_index.load(Ordering::Relaxed);
+
+                if latest_write_idx == cur_write_idx {
+                    return Err(value);
+                }
+
+                cur_write_idx = latest_write_idx;
+            } else {
+                // Another writer claimed the position already.
+                cur_write_idx = self.wr_index.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Takes the oldest element if it has been published, claiming its position with a
+    /// compare-exchange so any number of readers can call this concurrently.
+    ///
+    /// An element whose writer claimed its slot but hasn't finished writing it yet is not
+    /// readable, elements written after it by other writers have to wait for it.
+    pub(crate) fn pop(&self) -> Option<T> {
+        let mut cur_read_idx = self.rd_index.load(Ordering::Relaxed);
+
+        loop {
+            let slot = self.slot(cur_read_idx);
+            let stamp = slot.stamp.load(Ordering::Acquire);
+
+            if stamp == 2 * cur_read_idx + 1 {
+                match self.rd_index.compare_exchange_weak(
+                    cur_read_idx,
+                    cur_read_idx + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(self.take(slot, cur_read_idx)),
+                    Err(v) => cur_read_idx = v,
+                }
+            } else if (stamp.wrapping_sub(2 * cur_read_idx + 1) as i64) < 0 {
+                // The element hasn't been written yet. The ring is empty unless another reader
+                // moved on in the meantime.
+                let latest_read_idx = self.rd_index.load(Ordering::Relaxed);
+
+                if latest_read_idx == cur_read_idx {
+                    return None;
+                }
+
+                cur_read_idx = latest_read_idx;
+            } else {
+                // Another reader claimed the position already.
+                cur_read_idx = self.rd_index.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Like [`StampedSlots::pop`] but for a single reader, which can advance `rd_index` with a
+    /// plain store.
+    ///
+    /// # Safety
+    ///
+    /// No other thread may call `pop` or `pop_single` at the same time.
+    pub(crate) unsafe fn pop_single(&self) -> Option<T> {
+        let cur_read_idx = self.rd_index.load(Ordering::Relaxed);
+
+        let slot = self.slot(cur_read_idx);
+
+        if slot.stamp.load(Ordering::Acquire) != 2 * cur_read_idx + 1 {
+            return None;
+        }
+
+        let ret = self.take(slot, cur_read_idx);
+
+        self.rd_index.store(cur_read_idx + 1, Ordering::Release);
+
+        Some(ret)
+    }
+
+    /// Moves the element at the claimed position `index` out of `slot` and frees the slot for
+    /// the next lap.
+    fn take(&self, slot: &Slot<T>, index: u64) -> T {
+        let ret = unsafe { (*slot.value.get()).assume_init_read() };
+
+        slot.stamp
+            .store(2 * (index + self.slot_count), Ordering::Release);
+
+        ret
+    }
+}
+
+impl<T: Sized> Drop for StampedSlots<T> {
+    fn drop(&mut self) {
+        // Frees the storage even if dropping one of the remaining elements panics.
+        struct Dealloc<T>(NonNull<Slot<T>>, usize);
+
+        impl<T> Drop for Dealloc<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    dealloc_slots(self.0, self.1);
+                }
+            }
+        }
+
+        let _dealloc = Dealloc(self.slots, self.slot_count as usize);
+
+        // Writers may still have written elements after the readers drained the ring.
+        let mut cur_read_idx = self.rd_index.load(Ordering::Relaxed);
+        let cur_write_idx = self.wr_index.load(Ordering::Relaxed);
+
+        while cur_read_idx != cur_write_idx {
+            unsafe {
+                (*self.slot(cur_read_idx).value.get()).assume_init_drop();
+            }
+
+            cur_read_idx += 1;
+        }
+    }
+}
+
+// A slot is only accessed by the writer that claimed it until it is published, and by the
+// reader that claimed it afterwards.
+unsafe impl<T: Send> Send for StampedSlots<T> {}
+unsafe impl<T: Send> Sync for StampedSlots<T> {}
@@ -0,0 +1,302 @@
+//! Ring buffer with inline storage that never allocates.
+//!
+//! [`StaticRingBuffer`] uses the same free running indices as the rings created by
+//! [`create_ring_buffer`](crate::create_ring_buffer), but its slots live inside the value
+//! itself and the handles borrow it instead of sharing it through an `Arc`. Since
+//! [`StaticRingBuffer::new`] is a `const fn`, the ring can be placed in a `static`.
+//!
+//! ```
+//! use atomic_ring_buffer::StaticRingBuffer;
+//!
+//! let mut ring = StaticRingBuffer::<u32, 4>::new();
+//!
+//! let (mut buffer_writer, mut buffer_reader) = ring.split();
+//!
+//! std::thread::scope(|s| {
+//!     s.spawn(move || {
+//!         for idx in 0..100 {
+//!             while buffer_writer.try_write(idx).is_err() {
+//!                 std::thread::yield_now();
+//!             }
+//!         }
+//!     });
+//!
+//!     let mut expected = 0;
+//!
+//!     while expected < 100 {
+//!         if let Ok(v) = buffer_reader.try_read() {
+//!             assert_eq!(v, expected);
+//!
+//!             expected += 1;
+//!         }
+//!     }
+//! });
+//! ```
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use crate::atomic::{AtomicU64, Ordering};
+use crate::cache_padded::CachePadded;
+use crate::error::{TryReadError, TryWriteError};
+
+/// Ring buffer holding up to `N` elements in inline storage.
+///
+/// Neither side blocks or gets disconnected, the handles only offer the non-blocking
+/// operations. Elements still in the ring when the handles are dropped stay there and can be
+/// read after splitting it again, the rest is dropped together with the ring.
+pub struct StaticRingBuffer<T: Sized, const N: usize> {
+    wr_index: CachePadded<AtomicU64>,
+    rd_index: CachePadded<AtomicU64>,
+
+    slots: UnsafeCell<MaybeUninit<[T; N]>>,
+}
+
+/// Writing half of a [`StaticRingBuffer`], see [`StaticRingBuffer::split`].
+pub struct StaticBufferWriter<'a, T: Sized, const N: usize> {
+    ring: &'a StaticRingBuffer<T, N>,
+
+    cached_rd_index: u64,
+}
+
+/// Reading half of a [`StaticRingBuffer`], see [`StaticRingBuffer::split`].
+pub struct StaticBufferReader<'a, T: Sized, const N: usize> {
+    ring: &'a StaticRingBuffer<T, N>,
+
+    cached_wr_index: u64,
+}
+
+impl<T: Sized, const N: usize> StaticRingBuffer<T, N> {
+    /// Creates an empty ring.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `N` is zero, at compile time if the ring initializes a `static` or `const`.
+    pub const fn new() -> Self {
+        assert!(N > 0, "a ring buffer needs at least one slot");
+
+        StaticRingBuffer {
+            wr_index: CachePadded(AtomicU64::new(0)),
+            rd_index: CachePadded(AtomicU64::new(0)),
+            slots: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Splits the ring into a writer and a reader that can be moved to different threads.
+    pub fn split(&mut self) -> (StaticBufferWriter<'_, T, N>, StaticBufferReader<'_, T, N>) {
+        let cur_read_idx = *self.rd_index.0.get_mut();
+        let cur_write_idx = *self.wr_index.0.get_mut();
+
+        let ring = &*self;
+
+        (
+            StaticBufferWriter {
+                ring,
+                cached_rd_index: cur_read_idx,
+            },
+            StaticBufferReader {
+                ring,
+                cached_wr_index: cur_write_idx,
+            },
+        )
+    }
+
+    fn used_slots(cur_read_idx: u64, cur_write_idx: u64) -> usize {
+        cur_write_idx.wrapping_sub(cur_read_idx) as usize
+    }
+
+    fn slot_ptr(&self, index: u64) -> *mut T {
+        // `N` is a constant, so the modulo turns into a mask whenever it is a power of two.
+        unsafe { (self.slots.get() as *mut T).add((index % N as u64) as usize) }
+    }
+}
+
+impl<T: Sized, const N: usize> Default for StaticRingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Sized, const N: usize> Drop for StaticRingBuffer<T, N> {
+    fn drop(&mut self) {
+        let cur_read_idx = *self.rd_index.0.get_mut();
+        let cur_write_idx = *self.wr_index.0.get_mut();
+
+        let len = Self::used_slots(cur_read_idx, cur_write_idx);
+        let first = (cur_read_idx % N as u64) as usize;
+        let first_len = len.min(N - first);
+
+        unsafe {
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(
+                self.slot_ptr(cur_read_idx),
+                first_len,
+            ));
+            core::ptr::drop_in_place(core::ptr::slice_from_raw_parts_mut(
+                self.slot_ptr(cur_read_idx + first_len as u64),
+                len - first_len,
+            ));
+        }
+    }
+}
+
+// Shared access to the ring itself never touches the slots, only the handles do and each of them
+// exists once per split.
+unsafe impl<T: Send, const N: usize> Sync for StaticRingBuffer<T, N> {}
+
+impl<T: Sized, const N: usize> StaticBufferWriter<'_, T, N> {
+    pub fn size(&self) -> usize {
+        let cur_read_idx = self.ring.rd_index.load(Ordering::Acquire);
+        let cur_write_idx = self.ring.wr_index.load(Ordering::Relaxed);
+
+        StaticRingBuffer::<T, N>::used_slots(cur_read_idx, cur_write_idx)
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Writes `value` if there is a free slot, otherwise returns it back in
+    /// [`TryWriteError::Full`].
+    pub fn try_write(&mut self, value: T) -> Result<(), TryWriteError<T>> {
+        let cur_write_idx = self.ring.wr_index.load(Ordering::Relaxed);
+
+        if StaticRingBuffer::<T, N>::used_slots(self.cached_rd_index, cur_write_idx) == N {
+            self.cached_rd_index = self.ring.rd_index.load(Ordering::Acquire);
+
+            if StaticRingBuffer::<T, N>::used_slots(self.cached_rd_index, cur_write_idx) == N {
+                return Err(TryWriteError::Full(value));
+            }
+        }
+
+        unsafe {
+            core::ptr::write(self.ring.slot_ptr(cur_write_idx), value);
+        }
+
+        self.ring
+            .wr_index
+            .store(cur_write_idx.wrapping_add(1), Ordering::Release);
+
+        Ok(())
+    }
+}
+
+impl<T: Sized, const N: usize> StaticBufferReader<'_, T, N> {
+    pub fn size(&self) -> usize {
+        let cur_read_idx = self.ring.rd_index.load(Ordering::Relaxed);
+        let cur_write_idx = self.ring.wr_index.load(Ordering::Acquire);
+
+        StaticRingBuffer::<T, N>::used_slots(cur_read_idx, cur_write_idx)
+    }
+
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Reads the next element, fails with [`TryReadError::Empty`] if there is none.
+    pub fn try_read(&mut self) -> Result<T, TryReadError> {
+        let cur_read_idx = self.ring.rd_index.load(Ordering::Relaxed);
+
+        if cur_read_idx == self.cached_wr_index {
+            self.cached_wr_index = self.ring.wr_index.load(Ordering::Acquire);
+
+            if cur_read_idx == self.cached_wr_index {
+                return Err(TryReadError::Empty);
+            }
+        }
+
+        let ret = unsafe { core::ptr::read(self.ring.slot_ptr(cur_read_idx)) };
+
+        self.ring
+            .rd_index
+            .store(cur_read_idx.wrapping_add(1), Ordering::Release);
+
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::StaticRingBuffer;
+    use crate::{TryReadError, TryWriteError};
+
+    #[test]
+    fn static_ring_test() {
+        let mut ring = StaticRingBuffer::<u32, 3>::new();
+
+        let (mut buffer_writer, mut buffer_reader) = ring.split();
+
+        for idx in 0..10 {
+            assert!(buffer_writer.try_write(idx).is_ok());
+            assert!(buffer_writer.try_write(idx + 100).is_ok());
+            assert!(buffer_writer.try_write(idx + 200).is_ok());
+            assert_eq!(buffer_writer.try_write(0), Err(TryWriteError::Full(0)));
+            assert_eq!(buffer_reader.size(), 3);
+
+            assert_eq!(buffer_reader.try_read(), Ok(idx));
+            assert_eq!(buffer_reader.try_read(), Ok(idx + 100));
+            assert_eq!(buffer_reader.try_read(), Ok(idx + 200));
+            assert_eq!(buffer_reader.try_read(), Err(TryReadError::Empty));
+        }
+    }
+
+    static RING: Mutex<StaticRingBuffer<String, 4>> = Mutex::new(StaticRingBuffer::new());
+
+    #[test]
+    fn static_split_test() {
+        let mut ring = RING.lock().unwrap();
+
+        {
+            let (mut buffer_writer, _) = ring.split();
+
+            assert!(buffer_writer.try_write(String::from("kept")).is_ok());
+        }
+
+        let (_, mut buffer_reader) = ring.split();
+
+        assert_eq!(buffer_reader.try_read().as_deref(), Ok("kept"));
+    }
+
+    struct DropCounter(Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn static_drop_test() {
+        let drop_count = Arc::new(AtomicUsize::new(0));
+
+        let mut ring = StaticRingBuffer::<DropCounter, 4>::new();
+
+        let (mut buffer_writer, mut buffer_reader) = ring.split();
+
+        for _ in 0..3 {
+            assert!(buffer_writer
+                .try_write(DropCounter(drop_count.clone()))
+                .is_ok());
+        }
+
+        assert!(buffer_reader.try_read().is_ok());
+
+        for _ in 0..2 {
+            assert!(buffer_writer
+                .try_write(DropCounter(drop_count.clone()))
+                .is_ok());
+        }
+
+        assert_eq!(drop_count.load(Ordering::Relaxed), 1);
+
+        drop(ring);
+
+        assert_eq!(drop_count.load(Ordering::Relaxed), 5);
+    }
+}
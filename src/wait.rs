@@ -0,0 +1,196 @@
+use std::sync::atomic::{compiler_fence, fence, AtomicBool, AtomicU8, Ordering};
+use std::sync::{Mutex, Once};
+use std::task::Waker;
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+#[cfg(all(feature = "eventfd", target_os = "linux"))]
+use crate::readiness::Readiness;
+
+enum Waiter {
+    Thread(Thread),
+    Task(Waker),
+}
+
+/// Parking spot for the (single) thread or task blocked on one side of the ring.
+///
+/// The owning side announces itself with `prepare_wait`/`register_waker`, re-checks the ring and
+/// only then parks or returns `Poll::Pending`. The peer calls `notify` after publishing its index;
+/// the barriers on both sides make sure that either the waiter sees the new index or the
+/// notifier sees the waiting flag. `notify` runs for every published index, so it only issues
+/// [`light_barrier`] and leaves the expensive part of the handshake to the waiter's
+/// [`heavy_barrier`].
+///
+/// With the `eventfd` feature the slot also holds the readiness descriptor of its side, which
+/// `notify` signals if it has been armed.
+pub(crate) struct WaitSlot {
+    waiting: AtomicBool,
+    waiter: Mutex<Option<Waiter>>,
+
+    #[cfg(all(feature = "eventfd", target_os = "linux"))]
+    pub(crate) readiness: Readiness,
+}
+
+impl WaitSlot {
+    pub(crate) fn new() -> Self {
+        init_barriers();
+
+        WaitSlot {
+            waiting: AtomicBool::new(false),
+            waiter: Mutex::new(None),
+            #[cfg(all(feature = "eventfd", target_os = "linux"))]
+            readiness: Readiness::new(),
+        }
+    }
+
+    pub(crate) fn prepare_wait(&self) {
+        self.announce_wait();
+
+        heavy_barrier();
+    }
+
+    /// Like [`WaitSlot::prepare_wait`] but leaves the [`heavy_barrier`] to the caller, which
+    /// issues one for all the slots it waits on.
+    pub(crate) fn announce_wait(&self) {
+        *self.waiter.lock().unwrap() = Some(Waiter::Thread(thread::current()));
+
+        self.waiting.store(true, Ordering::Relaxed);
+    }
+
+    #[cfg_attr(not(feature = "async"), allow(dead_code))]
+    pub(crate) fn register_waker(&self, waker: &Waker) {
+        {
+            let mut waiter = self.waiter.lock().unwrap();
+
+            match waiter.as_mut() {
+                Some(Waiter::Task(current)) if current.will_wake(waker) => {}
+                _ => *waiter = Some(Waiter::Task(waker.clone())),
+            }
+        }
+
+        self.waiting.store(true, Ordering::Relaxed);
+
+        heavy_barrier();
+    }
+
+    pub(crate) fn cancel_wait(&self) {
+        self.waiting.store(false, Ordering::Relaxed);
+    }
+
+    /// Parks the current thread until it is notified or `deadline` has passed.
+    /// Spurious wakeups are possible, callers are expected to loop.
+    pub(crate) fn park(&self, deadline: Option<Instant>) {
+        park_until(deadline);
+
+        self.cancel_wait();
+    }
+
+    pub(crate) fn notify(&self) {
+        light_barrier();
+
+        #[cfg(all(feature = "eventfd", target_os = "linux"))]
+        self.readiness.signal();
+
+        if self.waiting.load(Ordering::Relaxed) {
+            match self.waiter.lock().unwrap().as_ref() {
+                Some(Waiter::Thread(thread)) => thread.unpark(),
+                Some(Waiter::Task(waker)) => waker.wake_by_ref(),
+                None => {}
+            }
+        }
+    }
+}
+
+/// Parks the current thread until it is unparked or `deadline` has passed, for waiting on
+/// several slots prepared with [`WaitSlot::prepare_wait`] at once.
+pub(crate) fn park_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => {
+            let now = Instant::now();
+
+            if deadline > now {
+                thread::park_timeout(deadline - now);
+            }
+        }
+        None => thread::park(),
+    }
+}
+
+const BARRIERS_SYMMETRIC: u8 = 1;
+const BARRIERS_ASYMMETRIC: u8 = 2;
+
+/// Kind of barriers in use, decided once before the first ring is created.
+static BARRIERS: AtomicU8 = AtomicU8::new(0);
+
+/// Decides between asymmetric and plain `SeqCst` barriers. Runs before any `WaitSlot` exists,
+/// so both sides of every handshake agree on the kind.
+fn init_barriers() {
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        let kind = if membarrier::register() {
+            BARRIERS_ASYMMETRIC
+        } else {
+            BARRIERS_SYMMETRIC
+        };
+
+        BARRIERS.store(kind, Ordering::Relaxed);
+    });
+}
+
+/// Barrier of the side that publishes an index and then checks for waiters. With asymmetric
+/// barriers it only keeps the compiler from reordering the two, [`heavy_barrier`] makes the
+/// hardware agree.
+pub(crate) fn light_barrier() {
+    if BARRIERS.load(Ordering::Relaxed) == BARRIERS_ASYMMETRIC {
+        compiler_fence(Ordering::SeqCst);
+    } else {
+        fence(Ordering::SeqCst);
+    }
+}
+
+/// Barrier of the side that announces a waiter and then re-checks the index of its peer.
+pub(crate) fn heavy_barrier() {
+    if BARRIERS.load(Ordering::Relaxed) == BARRIERS_ASYMMETRIC {
+        membarrier::barrier();
+    } else {
+        fence(Ordering::SeqCst);
+    }
+}
+
+/// `membarrier(2)` runs a memory barrier on every thread of the process that is running at the
+/// time, which makes up for the compiler fence those threads used instead. Miri can't model it,
+/// so it checks the code with the plain fences.
+#[cfg(all(target_os = "linux", not(miri)))]
+mod membarrier {
+    const MEMBARRIER_CMD_QUERY: libc::c_int = 0;
+    const MEMBARRIER_CMD_PRIVATE_EXPEDITED: libc::c_int = 1 << 3;
+    const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: libc::c_int = 1 << 4;
+
+    fn membarrier(cmd: libc::c_int) -> libc::c_long {
+        unsafe { libc::syscall(libc::SYS_membarrier, cmd, 0, 0) }
+    }
+
+    /// Returns true if the process can issue expedited barriers from now on.
+    pub(super) fn register() -> bool {
+        let supported = membarrier(MEMBARRIER_CMD_QUERY);
+
+        supported >= 0
+            && supported & MEMBARRIER_CMD_PRIVATE_EXPEDITED as libc::c_long != 0
+            && membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED) == 0
+    }
+
+    pub(super) fn barrier() {
+        // Can't fail once registered.
+        membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED);
+    }
+}
+
+#[cfg(any(not(target_os = "linux"), miri))]
+mod membarrier {
+    pub(super) fn register() -> bool {
+        false
+    }
+
+    pub(super) fn barrier() {}
+}